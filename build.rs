@@ -2,6 +2,11 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    // The pure Rust backend doesn't need the C sources
+    if env::var_os("CARGO_FEATURE_PURE_RUST").is_some() {
+        return;
+    }
+
     cc::Build::new()
         .shared_flag(true)
         .warnings(false)
@@ -1,19 +1,16 @@
 use cpal::traits::{DeviceTrait, HostTrait};
-use rustchord::{self, cc_to_rgb};
+use rustchord::{self, Frame, cc_to_rgb};
 use std::sync::mpsc::*;
 use std::thread;
 
 use piston_window::graphics::{clear, rectangle};
 use piston_window::*;
 
-struct NoteResult {
-    notes: Vec<rustchord::Note>,
-    folded: Vec<f32>,
-}
-
 fn main() {
-    let (tx, rx) = channel::<NoteResult>();
-    thread::spawn(move || audioprocess(tx));
+    let (tx, rx) = channel::<Frame>();
+    // Drawn frames are sent back to be refilled instead of allocating new ones
+    let (recycle_tx, recycle_rx) = channel::<Frame>();
+    thread::spawn(move || audioprocess(tx, recycle_rx));
     let mut window: PistonWindow = WindowSettings::new("colorchord binding demo", [1400, 480])
         .exit_on_esc(true)
         .build()
@@ -32,7 +29,7 @@ fn main() {
                 clear([0.; 4], graphics);
 
                 //Frequency bins
-                for (i, n) in v.folded.into_iter().enumerate() {
+                for (i, &n) in v.folded().iter().enumerate() {
                     let c = cc_to_rgb((i as f32 + 0.5) / 24., 1.0, 1.0);
 
                     rectangle(
@@ -43,7 +40,7 @@ fn main() {
                     );
                 }
 
-                for n in v.notes.into_iter() {
+                for n in v.notes() {
                     if !n.active {
                         continue;
                     }
@@ -62,14 +59,15 @@ fn main() {
                         graphics,
                     )
                 }
+
+                let _ = recycle_tx.send(v);
             }
         });
     }
 }
 
-fn audioprocess(c: Sender<NoteResult>) {
-    let (tx, rx) = channel();
-    let mut r = Ringbuffer::new(tx);
+fn audioprocess(c: Sender<Frame>, recycled: Receiver<Frame>) {
+    let (tx, rx) = channel::<Vec<f32>>();
     let host = cpal::default_host();
     let mut notefinder = rustchord::Notefinder::new(48000);
     let device = host
@@ -87,63 +85,21 @@ fn audioprocess(c: Sender<NoteResult>) {
     let _stream = device
         .build_input_stream(
             config,
-            move |data, inp: &cpal::InputCallbackInfo| r.audio_callback(data, inp),
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                let _ = tx.send(data.to_vec());
+            },
             err_fn,
             None,
         )
         .expect("Failed to build input stream");
 
     while let Ok(v) = rx.recv() {
-        notefinder.run(&v);
-
-        let m = NoteResult {
-            notes: notefinder.get_notes(),
-            folded: notefinder.get_folded().to_owned(),
-        };
-
-        c.send(m).expect("LUL");
-    }
-}
-
-const BUFFER_SIZE: usize = 8096;
-type Buffer = [f32; BUFFER_SIZE];
-
-pub struct Ringbuffer {
-    soundhead: usize,
-    buffer: Buffer,
-    nf: Sender<Vec<f32>>,
-}
-
-impl Ringbuffer {
-    fn new(nf: Sender<Vec<f32>>) -> Ringbuffer {
-        return Ringbuffer {
-            buffer: [0.0f32; BUFFER_SIZE],
-            soundhead: 0,
-            nf,
-        };
-    }
-
-    fn audio_callback(&mut self, mut input: &[f32], _info: &cpal::InputCallbackInfo) {
-        if input.len() > self.buffer.len() {
-            input = &input[..self.buffer.len()]
-        }
-
-        let old_head = self.soundhead;
-        self.soundhead += input.len();
-
-        if self.soundhead < self.buffer.len() {
-            self.buffer[old_head..self.soundhead].copy_from_slice(input);
-        } else {
-            self.soundhead %= self.buffer.len();
-            let first_len = self.buffer.len() - old_head;
-            self.buffer[old_head..].copy_from_slice(&input[..first_len]);
-            self.buffer[..self.soundhead].copy_from_slice(&input[first_len..]);
-        }
+        notefinder.push_samples(&v);
+        notefinder.process();
 
-        let mut out = Vec::with_capacity(self.buffer.len());
-        out.extend_from_slice(&self.buffer[self.soundhead..]);
-        out.extend_from_slice(&self.buffer[..self.soundhead]);
+        let mut frame = recycled.try_recv().unwrap_or_default();
+        notefinder.read_frame(&mut frame);
 
-        let _ = self.nf.send(out);
+        c.send(frame).expect("LUL");
     }
 }
@@ -0,0 +1,164 @@
+use super::Params;
+use crate::{Timing, internal};
+use std::slice;
+
+pub use internal::NoteDists;
+
+/// Owns a notefinder created by colorchord's `CreateNoteFinder`.
+pub struct NoteFinder {
+    nf: *mut internal::NoteFinder,
+}
+
+// SAFETY: The underlying C NoteFinder state is not thread-safe.
+// Send is safe because ownership can transfer between threads.
+// Sync is intentionally NOT implemented — concurrent access from
+// multiple threads would race on the C state.
+unsafe impl Send for NoteFinder {}
+
+macro_rules! params {
+    ($($field:ident),* $(,)?) => {
+        fn read_params(nf: &internal::NoteFinder) -> Params {
+            Params { $($field: nf.$field),* }
+        }
+
+        fn write_params(nf: &mut internal::NoteFinder, params: &Params) {
+            $(nf.$field = params.$field;)*
+        }
+    };
+}
+
+params!(
+    octaves,
+    freqbins,
+    base_hz,
+    filter_strength,
+    filter_iter,
+    decompose_iterations,
+    amplify,
+    compress_exponenet,
+    compress_coefficient,
+    dft_speedup,
+    dft_q,
+    default_sigma,
+    note_jumpability,
+    note_combine_distance,
+    slope,
+    note_attach_freq_iir,
+    note_attach_amp_iir,
+    note_attach_amp_iir2,
+    note_minimum_new_distribution_value,
+    note_out_chop,
+    dft_iir,
+    do_progressive_dft,
+);
+
+impl NoteFinder {
+    pub fn new(samplerate: i32) -> Option<NoteFinder> {
+        let nf = unsafe { internal::CreateNoteFinder(samplerate) };
+        (!nf.is_null()).then_some(NoteFinder { nf })
+    }
+
+    fn raw(&self) -> &internal::NoteFinder {
+        unsafe { &*self.nf }
+    }
+
+    pub fn params(&self) -> Params {
+        read_params(self.raw())
+    }
+
+    /// Settings are written through `&self`, the C code reads them on every run
+    pub fn set_params(&self, params: Params) {
+        unsafe { write_params(&mut *self.nf, &params) }
+    }
+
+    /// Reallocate the buffers and frequency table, see `ChangeNFParameters`
+    pub fn rebuild(&mut self) {
+        unsafe { internal::ChangeNFParameters(self.nf as *mut libc::c_void) }
+    }
+
+    /// Run the pipeline over a buffer with its write head at `head`
+    pub fn run(&mut self, samples: &[f32], head: usize) {
+        unsafe {
+            internal::RunNoteFinder(self.nf, samples.as_ptr(), head as i32, samples.len() as i32);
+        }
+    }
+
+    pub fn note_peaks(&self) -> usize {
+        self.raw().note_peaks as usize
+    }
+
+    pub fn note_positions(&self) -> &[f32] {
+        unsafe { slice::from_raw_parts(self.raw().note_positions, self.note_peaks()) }
+    }
+
+    pub fn note_amplitudes_out(&self) -> &[f32] {
+        unsafe { slice::from_raw_parts(self.raw().note_amplitudes_out, self.note_peaks()) }
+    }
+
+    pub fn note_amplitudes2(&self) -> &[f32] {
+        unsafe { slice::from_raw_parts(self.raw().note_amplitudes2, self.note_peaks()) }
+    }
+
+    pub fn enduring_note_id(&self) -> &[i32] {
+        unsafe { slice::from_raw_parts(self.raw().enduring_note_id, self.note_peaks()) }
+    }
+
+    /// Distributions found in the last run
+    pub fn dists(&self) -> &[NoteDists] {
+        unsafe { slice::from_raw_parts(self.raw().dists, self.raw().dists_count as usize) }
+    }
+
+    /// Distribution slots lined up with the note peaks
+    pub fn note_dists(&self) -> &[NoteDists] {
+        unsafe { slice::from_raw_parts(self.raw().dists, self.note_peaks()) }
+    }
+
+    pub fn folded_bins(&self) -> &[f32] {
+        unsafe { slice::from_raw_parts(self.raw().folded_bins, self.raw().freqbins as usize) }
+    }
+
+    pub fn outbins(&self) -> &[f32] {
+        let nf = self.raw();
+        unsafe { slice::from_raw_parts(nf.outbins, (nf.freqbins * nf.octaves) as usize) }
+    }
+
+    pub fn frequencies(&self) -> &[f32] {
+        let nf = self.raw();
+        unsafe { slice::from_raw_parts(nf.frequencies, (nf.freqbins * nf.octaves) as usize) }
+    }
+
+    pub fn sps_rec(&self) -> f32 {
+        self.raw().sps_rec
+    }
+
+    pub fn timing(&self) -> Timing {
+        let nf = self.raw();
+        Timing {
+            start: nf.StartTime,
+            dft: nf.DFTTime,
+            filter: nf.FilterTime,
+            decompose: nf.DecomposeTime,
+            finalize: nf.FinalizeTime,
+        }
+    }
+}
+
+impl Drop for NoteFinder {
+    fn drop(&mut self) {
+        unsafe {
+            let nf = &mut *self.nf;
+            libc::free(nf.note_positions as *mut libc::c_void);
+            libc::free(nf.note_amplitudes as *mut libc::c_void);
+            libc::free(nf.note_amplitudes_out as *mut libc::c_void);
+            libc::free(nf.note_amplitudes2 as *mut libc::c_void);
+            libc::free(nf.note_founds as *mut libc::c_void);
+            libc::free(nf.note_peaks_to_dists_mapping as *mut libc::c_void);
+            libc::free(nf.enduring_note_id as *mut libc::c_void);
+            libc::free(nf.frequencies as *mut libc::c_void);
+            libc::free(nf.outbins as *mut libc::c_void);
+            libc::free(nf.folded_bins as *mut libc::c_void);
+            libc::free(nf.dists as *mut libc::c_void);
+            libc::free(self.nf as *mut libc::c_void);
+        }
+    }
+}
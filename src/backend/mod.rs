@@ -0,0 +1,108 @@
+//! The implementation behind `Notefinder`.
+//!
+//! By default colorchord's C sources are compiled and called through bindgen, the
+//! `pure-rust` feature replaces them with a port in safe Rust. Both backends expose
+//! the same safe interface, so `Notefinder` doesn't know which one it's running on.
+
+#[cfg(not(feature = "pure-rust"))]
+mod ffi;
+// Also built for the tests of the C backend, which compare the two
+#[cfg(any(feature = "pure-rust", test))]
+#[cfg_attr(not(feature = "pure-rust"), allow(dead_code))]
+mod native;
+
+#[cfg(not(feature = "pure-rust"))]
+pub use ffi::{NoteDists, NoteFinder};
+#[cfg(feature = "pure-rust")]
+pub use native::{NoteDists, NoteFinder};
+
+/// Scalar settings of the notefinder, named after the fields of colorchord's `struct NoteFinder`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Params {
+    pub octaves: i32,
+    pub freqbins: i32,
+    pub base_hz: f32,
+    pub filter_strength: f32,
+    pub filter_iter: i32,
+    pub decompose_iterations: i32,
+    pub amplify: f32,
+    pub compress_exponenet: f32,
+    pub compress_coefficient: f32,
+    pub dft_speedup: f32,
+    pub dft_q: f32,
+    pub default_sigma: f32,
+    pub note_jumpability: f32,
+    pub note_combine_distance: f32,
+    pub slope: f32,
+    pub note_attach_freq_iir: f32,
+    pub note_attach_amp_iir: f32,
+    pub note_attach_amp_iir2: f32,
+    pub note_minimum_new_distribution_value: f32,
+    pub note_out_chop: f32,
+    pub dft_iir: f32,
+    pub do_progressive_dft: i32,
+}
+
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod tests {
+    use super::{ffi, native};
+    use std::f32::consts::TAU;
+
+    const SAMPLERATE: i32 = 16000;
+
+    /// A and E, the strongest notes of the signal
+    fn signal(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLERATE as f32;
+                0.5 * (TAU * 220.0 * t).sin() + 0.3 * (TAU * 329.63 * t).sin()
+            })
+            .collect()
+    }
+
+    /// Positions of the notes above `min_amplitude`, in the order the backend found them
+    fn notes(positions: &[f32], amplitudes: &[f32], min_amplitude: f32) -> Vec<f32> {
+        positions
+            .iter()
+            .zip(amplitudes)
+            .filter(|(_, amplitude)| **amplitude > min_amplitude)
+            .map(|(position, _)| *position)
+            .collect()
+    }
+
+    /// Timing holds absolute timestamps and is left out of the comparison
+    #[test]
+    fn backends_agree() {
+        let mut c = ffi::NoteFinder::new(SAMPLERATE).unwrap();
+        let mut rust = native::NoteFinder::new(SAMPLERATE).unwrap();
+        assert_eq!(c.params(), rust.params());
+        assert_eq!(c.frequencies(), rust.frequencies());
+
+        let input = signal(SAMPLERATE as usize * 2);
+        let mut buffer = vec![0.0; crate::RINGBUFFER_SIZE];
+        let mut head = 0;
+        for block in input.chunks(512) {
+            for sample in block {
+                buffer[head] = *sample;
+                head = (head + 1) % buffer.len();
+            }
+            c.run(&buffer, head);
+            rust.run(&buffer, head);
+        }
+
+        let peak = c.folded_bins().iter().copied().fold(0.0, f32::max);
+        assert!(peak > 0.0);
+        for (a, b) in c.folded_bins().iter().zip(rust.folded_bins()) {
+            assert!((a - b).abs() <= peak * 0.01, "folded bins differ: {a} {b}");
+        }
+
+        assert_eq!(c.note_peaks(), rust.note_peaks());
+        let c_notes = notes(c.note_positions(), c.note_amplitudes_out(), 0.01);
+        let rust_notes = notes(rust.note_positions(), rust.note_amplitudes_out(), 0.01);
+        assert!(!c_notes.is_empty());
+        assert_eq!(c_notes.len(), rust_notes.len());
+        for (a, b) in c_notes.iter().zip(&rust_notes) {
+            assert!((a - b).abs() < 0.05, "note positions differ: {a} {b}");
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Port of colorchord's `filter.c` and `decompose.c`.
+
+use super::NoteDists;
+
+/// Blur the folded bins with their neighbours, wrapping around the octave.
+///
+/// Every iteration mixes `strength` of the neighbours into each bin.
+pub(super) fn filter_folded_bins_blob(folded: &mut [f32], strength: f32, iter: i32) {
+    let bins = folded.len();
+    let mut tmp = vec![0.0; bins];
+    for _ in 0..iter {
+        tmp.copy_from_slice(folded);
+        for (i, bin) in folded.iter_mut().enumerate() {
+            let left = tmp[(i + bins - 1) % bins];
+            let right = tmp[(i + 1) % bins];
+            *bin = *bin * (1.0 - strength) + (left + right) * strength * 0.5;
+        }
+    }
+}
+
+/// Find the peaks of the folded histogram and describe each with a normal distribution.
+///
+/// The peak position is refined with its neighbours, unused slots get a mean of -1 and
+/// no amplitude. Returns the number of peaks found.
+pub(super) fn decompose_histogram(
+    histogram: &[f32],
+    out_dists: &mut [NoteDists],
+    default_sigma: f32,
+) -> usize {
+    let bins = histogram.len();
+    let mut peaks = 0;
+    for (i, &this) in histogram.iter().enumerate() {
+        if peaks == out_dists.len() {
+            break;
+        }
+
+        let prev = histogram[(i + bins - 1) % bins];
+        let next = histogram[(i + 1) % bins];
+        if prev > this || next > this || (prev == this && next == this) {
+            continue;
+        }
+
+        // 0.5 when both neighbours are equal, closer to the larger neighbour otherwise
+        let total_diff = (this - prev) + (this - next);
+        let offset = (this - prev) / total_diff - 0.5;
+
+        out_dists[peaks] = NoteDists {
+            amp: this * 4.0,
+            mean: i as f32 + offset,
+            sigma: default_sigma,
+            taken: 0,
+        };
+        peaks += 1;
+    }
+
+    for dist in &mut out_dists[peaks..] {
+        *dist = NoteDists {
+            amp: 0.0,
+            mean: -1.0,
+            sigma: default_sigma,
+            taken: 0,
+        };
+    }
+
+    peaks
+}
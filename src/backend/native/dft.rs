@@ -0,0 +1,341 @@
+//! Port of colorchord's `dft.c` and `DFT32.c`.
+//!
+//! `frequencies` hold the period of every bin in samples, `head` is the write head of
+//! the sample ringbuffer. The C versions keep the progressive state in statics, here it
+//! lives in `DftState` so every notefinder has its own.
+//!
+//! `DFTProgressiveInteger` and `DFTProgressiveIntegerSkippy` are fixed point versions of
+//! the progressive DFT for microcontrollers, they run the float progressive DFT here.
+
+use std::f32::consts::TAU;
+
+/// Decay of the progressive DFT accumulators per sample
+const PROGIIR: f32 = 0.005;
+
+#[derive(Default)]
+pub(super) struct DftState {
+    progressive: Progressive,
+    dft32: Dft32,
+}
+
+impl DftState {
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        &mut self,
+        algorithm: i32,
+        outbins: &mut [f32],
+        frequencies: &[f32],
+        data: &[f32],
+        head: usize,
+        q: f32,
+        speedup: f32,
+    ) {
+        if data.is_empty() {
+            return;
+        }
+        match algorithm {
+            0 => dft_quick(outbins, frequencies, data, head, q, speedup),
+            1..=3 => self.progressive.run(outbins, frequencies, data, head),
+            4 => self.dft32.run(outbins, frequencies, data, head),
+            _ => dft(outbins, frequencies, data, head, q),
+        }
+    }
+}
+
+/// Sine and cosine sums over `frequency * q` samples going back from `head`
+fn dft(outbins: &mut [f32], frequencies: &[f32], data: &[f32], head: usize, q: f32) {
+    let size = data.len();
+    for (out, &freq) in outbins.iter_mut().zip(frequencies) {
+        if !freq.is_finite() || freq <= 0.0 {
+            *out = 0.0;
+            continue;
+        }
+
+        let advance = TAU / freq;
+        let (mut phi, mut sin_sum, mut cos_sum) = (0.0f32, 0.0, 0.0);
+        let mut place = head % size;
+
+        let mut j = 0.0;
+        while j <= freq * q {
+            let sample = data[place];
+            place = (place + size - 1) % size;
+            sin_sum += phi.sin() * sample;
+            cos_sum += phi.cos() * sample;
+            phi += advance;
+            j += 1.0;
+        }
+
+        *out = (sin_sum * sin_sum + cos_sum * cos_sum).sqrt() / freq / q;
+    }
+}
+
+/// Like `dft` but only looks at every `skip`th sample, trading accuracy for speed
+fn dft_quick(
+    outbins: &mut [f32],
+    frequencies: &[f32],
+    data: &[f32],
+    head: usize,
+    q: f32,
+    speedup: f32,
+) {
+    let size = data.len();
+    for (out, &freq) in outbins.iter_mut().zip(frequencies) {
+        if !freq.is_finite() || freq <= 0.0 {
+            *out = 0.0;
+            continue;
+        }
+
+        let ftq = (freq * q) as i64;
+        let skip = ((ftq as f32 / speedup).floor() as i64).max(1);
+        let advance = TAU / freq * skip as f32;
+        let (mut phi, mut sin_sum, mut cos_sum) = (0.0f32, 0.0, 0.0);
+        let mut place = head % size;
+
+        let mut j = 0;
+        while j <= ftq {
+            let sample = data[place];
+            place = (place + size * skip as usize - skip as usize) % size;
+            sin_sum += phi.sin() * sample;
+            cos_sum += phi.cos() * sample;
+            phi += advance;
+            j += skip;
+        }
+
+        *out = (sin_sum * sin_sum + cos_sum * cos_sum).sqrt() / freq / q * skip as f32;
+    }
+}
+
+/// Running DFT updated with every new sample through an IIR filter
+#[derive(Default)]
+struct Progressive {
+    sin_sums: Vec<f32>,
+    cos_sums: Vec<f32>,
+    phis: Vec<f32>,
+    advances: Vec<f32>,
+    lastbins: Vec<f32>,
+    last_place: usize,
+}
+
+impl Progressive {
+    fn run(&mut self, outbins: &mut [f32], frequencies: &[f32], data: &[f32], head: usize) {
+        let bins = frequencies.len();
+        if self.lastbins.len() != bins {
+            self.sin_sums = vec![0.0; bins];
+            self.cos_sums = vec![0.0; bins];
+            self.phis = vec![0.0; bins];
+            self.lastbins = vec![0.0; bins];
+        }
+        outbins.copy_from_slice(&self.lastbins);
+        self.advances = frequencies.iter().map(|f| TAU / f).collect();
+
+        let size = data.len();
+        let head = head % size;
+        let mut place = self.last_place % size;
+        let mut didrun = false;
+        while place != head {
+            // Simulate 8 bit input like colorchord does
+            let sample = ((data[place] * 127.0) as i32) as f32 / 127.0;
+            self.handle(sample, outbins);
+            place = (place + 1) % size;
+            didrun = true;
+        }
+        self.last_place = head;
+
+        if didrun {
+            self.lastbins.copy_from_slice(outbins);
+        }
+    }
+
+    fn handle(&mut self, sample: f32, outbins: &mut [f32]) {
+        for (i, out) in outbins.iter_mut().enumerate() {
+            let phi = self.phis[i];
+            let s = self.sin_sums[i] * (1.0 - PROGIIR) + phi.sin() * sample * PROGIIR;
+            let c = self.cos_sums[i] * (1.0 - PROGIIR) + phi.cos() * sample * PROGIIR;
+            self.sin_sums[i] = s;
+            self.cos_sums[i] = c;
+
+            self.phis[i] += self.advances[i];
+            if self.phis[i] > TAU {
+                self.phis[i] -= TAU;
+            }
+
+            *out = (s * s + c * c).sqrt();
+        }
+    }
+}
+
+const OCTAVES: usize = 5;
+const FIXBPERO: usize = 24;
+const FIXBINS: usize = FIXBPERO * OCTAVES;
+const BINCYCLE: usize = 1 << OCTAVES;
+/// Accumulators decay by 1 / 2^DFTIIR every update
+const DFTIIR: u32 = 6;
+
+/// A quarter of a sine wave is 64 entries, values range from -1500 to 1500
+fn sin_table() -> [i16; 256] {
+    std::array::from_fn(|i| (1500.0 * (i as f32 * TAU / 256.0).sin()).round() as i16)
+}
+
+/// Fixed point progressive DFT from colorchord's embedded builds.
+///
+/// The highest octave is updated every sample, every lower octave half as often with
+/// averaged samples. Only works with 5 octaves of 24 bins.
+struct Dft32 {
+    sin_table: [i16; 256],
+    /// Octave updated at every step of the cycle, 255 updates the output
+    schedule: [u8; BINCYCLE],
+    step: usize,
+    octave_accumulators: [i32; OCTAVES],
+    /// Phase advance and phase of every bin, 8 bit integer part and 8 bit fraction
+    advances: [u16; FIXBINS],
+    places: [u16; FIXBINS],
+    /// Sine and cosine sums of every bin
+    sums: [i32; FIXBINS * 2],
+    sums_out: [i32; FIXBINS * 2],
+    backupbins: [f32; FIXBINS],
+    last_place: usize,
+}
+
+impl Default for Dft32 {
+    fn default() -> Dft32 {
+        // 255 4 3 4 2 4 3 4 1 4 3 4 2 4 3 4 0 4 3 4 2 4 3 4 1 4 3 4 2 4 3 4
+        let mut schedule = [0xff; BINCYCLE];
+        for i in 0..BINCYCLE - 1 {
+            let first_zero = (0..=OCTAVES).find(|j| (1 << j) & i == 0).unwrap_or(OCTAVES);
+            schedule[i + 1] = (OCTAVES - first_zero - 1) as u8;
+        }
+
+        Dft32 {
+            sin_table: sin_table(),
+            schedule,
+            step: 0,
+            octave_accumulators: [0; OCTAVES],
+            advances: [0; FIXBINS],
+            places: [0; FIXBINS],
+            sums: [0; FIXBINS * 2],
+            sums_out: [0; FIXBINS * 2],
+            backupbins: [0.0; FIXBINS],
+            last_place: 0,
+        }
+    }
+}
+
+impl Dft32 {
+    fn run(&mut self, outbins: &mut [f32], frequencies: &[f32], data: &[f32], head: usize) {
+        outbins.fill(0.0);
+        let len = outbins.len().min(FIXBINS);
+        outbins[..len].copy_from_slice(&self.backupbins[..len]);
+        if outbins.len() != FIXBINS || frequencies.len() != FIXBINS {
+            return;
+        }
+
+        // Every octave uses the advances of the top octave at a lower rate
+        for (i, advance) in self.advances.iter_mut().enumerate() {
+            let freq = frequencies[(i % FIXBPERO) + FIXBPERO * (OCTAVES - 1)];
+            *advance = (65536.0 / freq) as u16;
+        }
+
+        let size = data.len();
+        let head = head % size;
+        let mut place = self.last_place % size;
+        while place != head {
+            let sample = (data[place] * 4095.0) as i16;
+            self.handle(sample);
+            self.handle(sample);
+            place = (place + 1) % size;
+        }
+        self.last_place = head;
+
+        self.update_output(outbins);
+        self.backupbins.copy_from_slice(outbins);
+    }
+
+    fn handle(&mut self, sample: i16) {
+        let octave = self.schedule[self.step];
+        self.step = (self.step + 1) % BINCYCLE;
+
+        for accumulator in &mut self.octave_accumulators {
+            *accumulator = accumulator.wrapping_add(sample as i32);
+        }
+
+        if octave > 128 {
+            // Once every cycle the sums are published and decayed
+            for (sum, out) in self.sums.iter_mut().zip(&mut self.sums_out) {
+                *out = *sum;
+                *sum = sum.wrapping_sub(*sum >> DFTIIR);
+            }
+            return;
+        }
+
+        let octave = octave as usize;
+        let filtered = (self.octave_accumulators[octave] >> (OCTAVES - octave)) as i16 as i32;
+        self.octave_accumulators[octave] = 0;
+
+        for i in octave * FIXBPERO..(octave + 1) * FIXBPERO {
+            let phase = (self.places[i] >> 8) as u8;
+            self.places[i] = self.places[i].wrapping_add(self.advances[i]);
+
+            let sin = self.sin_table[phase as usize] as i32;
+            // Cosine is a quarter wave ahead of the sine
+            let cos = self.sin_table[phase.wrapping_add(64) as usize] as i32;
+            self.sums[i * 2] = self.sums[i * 2].wrapping_add(sin * filtered);
+            self.sums[i * 2 + 1] = self.sums[i * 2 + 1].wrapping_add(cos * filtered);
+        }
+    }
+
+    fn update_output(&self, outbins: &mut [f32]) {
+        for (i, out) in outbins.iter_mut().enumerate() {
+            let sin = self.sums_out[i * 2].unsigned_abs() as f32;
+            let cos = self.sums_out[i * 2 + 1].unsigned_abs() as f32;
+            let octave = i / FIXBPERO;
+
+            *out = (sin * sin + cos * cos).sqrt() / 65536.0;
+            *out /= ((78 << DFTIIR) * (1 << octave)) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progressive_stops_before_head() {
+        let frequencies = [8.0, 16.0];
+        let mut data = [0.0; 64];
+        // Not written yet, `head` is where the next sample goes
+        data[10] = 1.0;
+
+        let mut progressive = Progressive::default();
+        let mut outbins = [0.0; 2];
+        progressive.run(&mut outbins, &frequencies, &data, 10);
+        assert_eq!(outbins, [0.0; 2]);
+
+        data[9] = 1.0;
+        progressive.last_place = 9;
+        progressive.run(&mut outbins, &frequencies, &data, 10);
+        assert!(outbins.iter().all(|bin| *bin > 0.0));
+    }
+
+    #[test]
+    fn dft_finds_sine() {
+        let period = 16.0;
+        let data: Vec<f32> = (0..1024).map(|i| (TAU * i as f32 / period).sin()).collect();
+        let frequencies = [period / 2.0, period, period * 2.0];
+        let mut outbins = [0.0; 3];
+        dft(&mut outbins, &frequencies, &data, data.len() - 1, 16.0);
+        assert!(outbins[1] > outbins[0] * 4.0 && outbins[1] > outbins[2] * 4.0);
+    }
+
+    #[test]
+    fn dft_skips_invalid_periods() {
+        let data = [0.5; 64];
+        let frequencies = [f32::INFINITY, 0.0, f32::NAN];
+        let mut outbins = [1.0; 3];
+        dft(&mut outbins, &frequencies, &data, 0, 16.0);
+        assert_eq!(outbins, [0.0; 3]);
+        outbins = [1.0; 3];
+        dft_quick(&mut outbins, &frequencies, &data, 0, 16.0, 300.0);
+        assert_eq!(outbins, [0.0; 3]);
+    }
+}
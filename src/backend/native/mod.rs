@@ -0,0 +1,429 @@
+//! Safe Rust port of colorchord's `notefinder.c`.
+//!
+//! Follows the C pipeline step by step so both backends produce the same notes: DFT,
+//! IIR and tapering of the bins, folding into one octave, blob filter, decomposition
+//! into normal distributions and finally matching the distributions to notes.
+
+mod decompose;
+mod dft;
+
+use super::Params;
+use crate::Timing;
+use std::cell::Cell;
+
+/// Notes below this amplitude are considered dead, `note_minimum_amplitude` in colorchord
+const NOTE_MIN_AMPLITUDE: f32 = 0.001;
+
+/// A peak of the folded bins described as a normal distribution, laid out like colorchord's
+/// `struct NoteDists`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoteDists {
+    pub amp: f32,
+    pub mean: f32,
+    pub sigma: f32,
+    pub taken: u8,
+}
+
+pub struct NoteFinder {
+    /// Settings are written through `&self` like the C fields, hence the `Cell`
+    params: Cell<Params>,
+    sps_rec: f32,
+    /// Size the buffers were allocated for, changed by `rebuild`
+    freqbins: usize,
+    octaves: usize,
+    note_peaks: usize,
+    current_note_id: i32,
+
+    note_positions: Vec<f32>,
+    note_amplitudes: Vec<f32>,
+    note_amplitudes_out: Vec<f32>,
+    note_amplitudes2: Vec<f32>,
+    note_founds: Vec<bool>,
+    note_peaks_to_dists_mapping: Vec<Option<usize>>,
+    enduring_note_id: Vec<i32>,
+
+    frequencies: Vec<f32>,
+    outbins: Vec<f32>,
+    folded_bins: Vec<f32>,
+    dists: Vec<NoteDists>,
+    dists_count: usize,
+
+    dft: dft::DftState,
+    dftbins: Vec<f32>,
+    timing: Timing,
+}
+
+impl NoteFinder {
+    /// Create a notefinder with colorchord's default settings, see `CreateNoteFinder`
+    pub fn new(samplerate: i32) -> Option<NoteFinder> {
+        let params = Params {
+            octaves: 5,
+            freqbins: 24,
+            base_hz: 55.0,
+            filter_strength: 0.5,
+            filter_iter: 1,
+            decompose_iterations: 1000,
+            amplify: 1.0,
+            compress_exponenet: 0.0,
+            compress_coefficient: 1.0,
+            dft_speedup: 300.0,
+            dft_q: 16.0,
+            default_sigma: 1.4,
+            note_jumpability: 2.5,
+            note_combine_distance: 0.5,
+            slope: 0.0,
+            note_attach_freq_iir: 0.3,
+            note_attach_amp_iir: 0.2,
+            note_attach_amp_iir2: 0.05,
+            note_minimum_new_distribution_value: 0.02,
+            note_out_chop: 0.1,
+            dft_iir: 0.0,
+            do_progressive_dft: 0,
+        };
+
+        let mut nf = NoteFinder {
+            params: Cell::new(params),
+            sps_rec: samplerate as f32,
+            freqbins: 0,
+            octaves: 0,
+            note_peaks: 0,
+            current_note_id: 1,
+            note_positions: Vec::new(),
+            note_amplitudes: Vec::new(),
+            note_amplitudes_out: Vec::new(),
+            note_amplitudes2: Vec::new(),
+            note_founds: Vec::new(),
+            note_peaks_to_dists_mapping: Vec::new(),
+            enduring_note_id: Vec::new(),
+            frequencies: Vec::new(),
+            outbins: Vec::new(),
+            folded_bins: Vec::new(),
+            dists: Vec::new(),
+            dists_count: 0,
+            dft: dft::DftState::default(),
+            dftbins: Vec::new(),
+            timing: Timing::default(),
+        };
+        nf.rebuild();
+        Some(nf)
+    }
+
+    pub fn params(&self) -> Params {
+        self.params.get()
+    }
+
+    /// Settings are read on every run, structural ones only take effect after `rebuild`
+    pub fn set_params(&self, params: Params) {
+        self.params.set(params)
+    }
+
+    /// Reallocate the buffers and frequency table, see `ChangeNFParameters`
+    pub fn rebuild(&mut self) {
+        let params = self.params.get();
+        let freqbins = params.freqbins.max(1) as usize;
+        let octaves = params.octaves.max(1) as usize;
+        let freqs = freqbins * octaves;
+
+        if freqbins != self.freqbins || octaves != self.octaves {
+            self.freqbins = freqbins;
+            self.octaves = octaves;
+            self.note_peaks = freqbins / 2;
+
+            let note_peaks = self.note_peaks;
+            self.note_positions = vec![0.0; note_peaks];
+            self.note_amplitudes = vec![0.0; note_peaks];
+            self.note_amplitudes_out = vec![0.0; note_peaks];
+            self.note_amplitudes2 = vec![0.0; note_peaks];
+            self.note_founds = vec![false; note_peaks];
+            self.note_peaks_to_dists_mapping = vec![None; note_peaks];
+            self.enduring_note_id = vec![0; note_peaks];
+
+            self.outbins = vec![0.0; freqs];
+            self.dftbins = vec![0.0; freqs];
+            self.folded_bins = vec![0.0; freqbins];
+            self.dists = vec![NoteDists::default(); freqbins];
+            self.dists_count = 0;
+        }
+
+        // Periods of every bin in samples
+        let base = self.sps_rec / params.base_hz;
+        self.frequencies = (0..freqs)
+            .map(|i| base / 2f32.powf(i as f32 / freqbins as f32))
+            .collect();
+    }
+
+    /// Run the pipeline over a buffer with its write head at `head`, see `RunNoteFinder`
+    pub fn run(&mut self, samples: &[f32], head: usize) {
+        let params = self.params.get();
+        let freqbins = self.freqbins;
+        let freqs = freqbins * self.octaves;
+
+        self.timing.start = now();
+
+        self.dftbins.fill(0.0);
+        self.dft.run(
+            params.do_progressive_dft,
+            &mut self.dftbins,
+            &self.frequencies,
+            samples,
+            head,
+            params.dft_q,
+            params.dft_speedup,
+        );
+
+        for (i, (out, dft)) in self.outbins.iter_mut().zip(&self.dftbins).enumerate() {
+            let amplified = dft * (1.0 - params.dft_iir) * params.amplify;
+            *out = *out * params.dft_iir + amplified * (1.0 + params.slope * i as f32);
+        }
+
+        // Taper the edges so notes don't pop in and out at the ends of the range
+        for i in 0..freqbins {
+            let taper = (i + 1) as f32 / freqbins as f32;
+            self.outbins[i] *= taper;
+            self.outbins[freqs - i - 1] *= taper;
+        }
+
+        self.timing.dft = now();
+
+        for (i, folded) in self.folded_bins.iter_mut().enumerate() {
+            *folded = self.outbins[i..].iter().step_by(freqbins).sum();
+        }
+        decompose::filter_folded_bins_blob(
+            &mut self.folded_bins,
+            params.filter_strength,
+            params.filter_iter,
+        );
+
+        self.timing.filter = now();
+
+        let note_peaks = self.note_peaks;
+        self.dists_count = decompose::decompose_histogram(
+            &self.folded_bins,
+            &mut self.dists[..note_peaks],
+            params.default_sigma,
+        );
+
+        self.timing.decompose = now();
+
+        self.compress_dists(&params);
+        self.match_notes(&params);
+
+        for (out, &amp) in self
+            .note_amplitudes_out
+            .iter_mut()
+            .zip(&self.note_amplitudes)
+        {
+            *out = (amp - params.note_out_chop).max(0.0);
+        }
+
+        self.timing.finalize = now();
+    }
+
+    /// Normalize the amplitudes of the distributions with the compressor settings
+    fn compress_dists(&mut self, params: &Params) {
+        let dists = &mut self.dists[..self.dists_count];
+        let total: f32 = dists.iter().map(|d| d.amp).sum();
+        if total <= 0.0 {
+            return;
+        }
+        let muxer = params.compress_coefficient
+            / (total * params.compress_coefficient).powf(params.compress_exponenet);
+        for dist in dists {
+            dist.amp *= muxer;
+        }
+    }
+
+    /// Attach the distributions to existing notes, combine notes that got too close,
+    /// start new notes and decay the ones that found no distribution
+    fn match_notes(&mut self, params: &Params) {
+        let bins = self.freqbins as f32;
+        let dists = &mut self.dists[..self.dists_count];
+        self.note_founds.fill(false);
+        self.note_peaks_to_dists_mapping.fill(None);
+
+        for i in 0..self.note_peaks {
+            let position = self.note_positions[i];
+            let closest = dists
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| d.amp > 0.00001)
+                .map(|(j, d)| (j, fabsloop(position, d.mean, bins)))
+                .filter(|&(_, distance)| distance < params.note_jumpability)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            if let Some((j, _)) = closest {
+                let dist = &mut dists[j];
+                self.note_founds[i] = true;
+                self.note_peaks_to_dists_mapping[i] = Some(j);
+                dist.taken = 1;
+
+                self.note_positions[i] = avgloop(
+                    position,
+                    1.0 - params.note_attach_freq_iir,
+                    dist.mean,
+                    params.note_attach_freq_iir,
+                    bins,
+                );
+                self.note_amplitudes[i] = self.note_amplitudes[i]
+                    * (1.0 - params.note_attach_amp_iir)
+                    + dist.amp * params.note_attach_amp_iir;
+            }
+        }
+
+        // Merge notes that drifted onto each other into the louder one
+        for i in 0..self.note_peaks {
+            for j in 0..i {
+                let (amp_i, amp_j) = (self.note_amplitudes[i], self.note_amplitudes[j]);
+                if amp_i <= 0.0 || amp_j <= 0.0 {
+                    continue;
+                }
+                let distance = fabsloop(self.note_positions[i], self.note_positions[j], bins);
+                if distance >= params.note_combine_distance {
+                    continue;
+                }
+
+                let (keep, drop) = if amp_i > amp_j { (i, j) } else { (j, i) };
+                self.note_positions[keep] = avgloop(
+                    self.note_positions[keep],
+                    self.note_amplitudes[keep],
+                    self.note_positions[drop],
+                    self.note_amplitudes[drop],
+                    bins,
+                );
+                self.note_amplitudes[keep] += self.note_amplitudes[drop];
+                self.note_amplitudes[drop] = 0.0;
+                self.note_amplitudes2[drop] = 0.0;
+                self.note_positions[drop] = -1.0;
+                self.note_founds[drop] = false;
+                self.enduring_note_id[drop] = 0;
+            }
+        }
+
+        // Give distributions nobody claimed to dead notes
+        for (j, dist) in dists.iter_mut().enumerate() {
+            if dist.taken != 0 || dist.amp < params.note_minimum_new_distribution_value {
+                continue;
+            }
+            let Some(i) = self
+                .note_amplitudes
+                .iter()
+                .position(|&amp| amp < NOTE_MIN_AMPLITUDE)
+            else {
+                break;
+            };
+
+            dist.taken = 1;
+            self.note_founds[i] = true;
+            self.note_peaks_to_dists_mapping[i] = Some(j);
+            self.note_positions[i] = dist.mean;
+            self.note_amplitudes[i] = dist.amp;
+            self.note_amplitudes2[i] = dist.amp;
+            self.enduring_note_id[i] = self.current_note_id;
+            self.current_note_id += 1;
+        }
+
+        for i in 0..self.note_peaks {
+            if !self.note_founds[i] {
+                self.note_amplitudes[i] *= 1.0 - params.note_attach_amp_iir;
+            }
+            self.note_amplitudes2[i] = self.note_amplitudes2[i]
+                * (1.0 - params.note_attach_amp_iir2)
+                + self.note_amplitudes[i] * params.note_attach_amp_iir2;
+
+            if self.note_amplitudes[i] < NOTE_MIN_AMPLITUDE {
+                self.note_amplitudes[i] = 0.0;
+                self.note_amplitudes2[i] = 0.0;
+            }
+        }
+    }
+
+    pub fn note_peaks(&self) -> usize {
+        self.note_peaks
+    }
+
+    pub fn note_positions(&self) -> &[f32] {
+        &self.note_positions
+    }
+
+    pub fn note_amplitudes_out(&self) -> &[f32] {
+        &self.note_amplitudes_out
+    }
+
+    pub fn note_amplitudes2(&self) -> &[f32] {
+        &self.note_amplitudes2
+    }
+
+    pub fn enduring_note_id(&self) -> &[i32] {
+        &self.enduring_note_id
+    }
+
+    /// Distributions found in the last run
+    pub fn dists(&self) -> &[NoteDists] {
+        &self.dists[..self.dists_count]
+    }
+
+    /// Distribution slots lined up with the note peaks
+    pub fn note_dists(&self) -> &[NoteDists] {
+        &self.dists[..self.note_peaks]
+    }
+
+    pub fn folded_bins(&self) -> &[f32] {
+        &self.folded_bins
+    }
+
+    pub fn outbins(&self) -> &[f32] {
+        &self.outbins
+    }
+
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    pub fn sps_rec(&self) -> f32 {
+        self.sps_rec
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+}
+
+/// Distance between two positions on a circle of `modulus` bins
+fn fabsloop(a: f32, b: f32, modulus: f32) -> f32 {
+    let distance = (a - b).abs() % modulus;
+    if distance > modulus / 2.0 {
+        modulus - distance
+    } else {
+        distance
+    }
+}
+
+/// Weighted average of two positions on a circle of `modulus` bins
+fn avgloop(mut a: f32, wa: f32, mut b: f32, wb: f32, modulus: f32) -> f32 {
+    if wa + wb <= 0.0 {
+        return a;
+    }
+    // Average across the wrap point instead of through the middle of the octave
+    if (a - b).abs() > modulus / 2.0 {
+        if a < b {
+            a += modulus;
+        } else {
+            b += modulus;
+        }
+    }
+    ((a * wa + b * wb) / (wa + wb)).rem_euclid(modulus)
+}
+
+/// Seconds since the epoch like colorchord's `OGGetAbsoluteTime`, the timers read 0
+/// where no clock is available
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn now() -> f64 {
+    0.0
+}
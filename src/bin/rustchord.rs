@@ -0,0 +1,499 @@
+//! Offline analysis of audio files.
+//!
+//! Runs the notefinder over a WAV file or raw PCM with a fixed hop size and writes the notes,
+//! folded bins and optionally the stage timings of every frame as JSON Lines or CSV.
+
+use rustchord::conf::ColorchordConf;
+use rustchord::input::{Downmix, PcmFormat, PcmReader};
+use rustchord::{Note, Notefinder, RINGBUFFER_SIZE, ResampleQuality, Timing};
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+const USAGE: &str = "\
+Usage: rustchord [OPTIONS] <INPUT>
+
+INPUT is a WAV file, or raw PCM when --raw is given. Use - to read from stdin.
+
+Options:
+  --raw <FORMAT>      Read raw interleaved PCM: s16le, s32le, f32le or u8
+  --channels <N>      Channels of raw PCM [default: 1]
+  --rate <HZ>         Sample rate of raw PCM [default: 48000]
+  --downmix <MODE>    Reduce channels with average, left, right, mid or side [default: average]
+  --analysis-rate <HZ>
+                      Resample the input to this rate before analysis
+  --resample <QUALITY>
+                      Resampling quality, fast, balanced or high [default: balanced]
+  --hop <SAMPLES>     Samples per analysis frame, at most 8095 [default: 512]
+  --format <FORMAT>   Output format, jsonl or csv [default: jsonl]
+  --timing            Add the seconds every pipeline stage took to each frame
+  --config <FILE>     Colorchord .conf file with notefinder settings
+  --output <FILE>     Write to a file instead of stdout
+  -h, --help          Print this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Jsonl,
+    Csv,
+}
+
+/// Layout of raw PCM input
+struct RawInput {
+    format: PcmFormat,
+    channels: usize,
+    samplerate: u32,
+}
+
+struct Args {
+    input: String,
+    raw: Option<RawInput>,
+    downmix: Downmix,
+    analysis_rate: Option<i32>,
+    resample: ResampleQuality,
+    hop: usize,
+    format: Format,
+    timing: bool,
+    config: Option<String>,
+    output: Option<String>,
+}
+
+fn positive<T: std::str::FromStr + PartialOrd + Default>(
+    name: &str,
+    value: String,
+) -> Result<T, String> {
+    match value.parse() {
+        Ok(v) if v > T::default() => Ok(v),
+        _ => Err(format!("{name} must be a positive number")),
+    }
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut input = None;
+    let mut raw_format = None;
+    let mut channels = 1;
+    let mut samplerate = 48000;
+    let mut downmix = Downmix::default();
+    let mut analysis_rate = None;
+    let mut resample = ResampleQuality::default();
+    let mut hop = 512;
+    let mut format = Format::Jsonl;
+    let mut timing = false;
+    let mut config = None;
+    let mut output = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "--hop" => {
+                hop = positive("--hop", value("--hop")?)?;
+                // The ringbuffer drops samples once more than this are pushed between runs
+                if hop > RINGBUFFER_SIZE - 1 {
+                    return Err(format!("--hop must be at most {}", RINGBUFFER_SIZE - 1));
+                }
+            }
+            "--raw" => raw_format = Some(value("--raw")?.parse().map_err(|e| format!("{e}"))?),
+            "--channels" => channels = positive("--channels", value("--channels")?)?,
+            "--rate" => samplerate = positive("--rate", value("--rate")?)?,
+            "--downmix" => downmix = value("--downmix")?.parse().map_err(|e| format!("{e}"))?,
+            "--analysis-rate" => {
+                analysis_rate = Some(positive("--analysis-rate", value("--analysis-rate")?)?)
+            }
+            "--resample" => {
+                resample = match value("--resample")?.as_str() {
+                    "fast" => ResampleQuality::Fast,
+                    "balanced" => ResampleQuality::Balanced,
+                    "high" => ResampleQuality::High,
+                    other => return Err(format!("unknown resample quality {other}")),
+                }
+            }
+            "--format" => {
+                format = match value("--format")?.as_str() {
+                    "jsonl" => Format::Jsonl,
+                    "csv" => Format::Csv,
+                    other => return Err(format!("unknown format {other}")),
+                }
+            }
+            "--timing" => timing = true,
+            "--config" => config = Some(value("--config")?),
+            "--output" => output = Some(value("--output")?),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {arg}")),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+
+    Ok(Args {
+        input: input.ok_or("missing input file")?,
+        raw: raw_format.map(|format| RawInput {
+            format,
+            channels,
+            samplerate,
+        }),
+        downmix,
+        analysis_rate,
+        resample,
+        hop,
+        format,
+        timing,
+        config,
+        output,
+    })
+}
+
+/// Seconds each pipeline stage of a run took
+#[derive(Debug, Clone, Copy, Serialize)]
+struct StageTiming {
+    dft: f64,
+    filter: f64,
+    decompose: f64,
+    finalize: f64,
+}
+
+impl StageTiming {
+    /// The notefinder timers are absolute clock readings, only their differences are
+    /// comparable between runs
+    fn from_timing(t: Timing) -> StageTiming {
+        StageTiming {
+            dft: t.dft - t.start,
+            filter: t.filter - t.dft,
+            decompose: t.decompose - t.filter,
+            finalize: t.finalize - t.decompose,
+        }
+    }
+}
+
+/// A single analysis frame as written to the output
+#[derive(Serialize)]
+struct FrameRecord<'a> {
+    frame: u64,
+    /// Position of the end of the frame in the input, in seconds
+    time: f64,
+    notes: &'a [Note],
+    folded: &'a [f32],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timing: Option<StageTiming>,
+}
+
+struct FrameWriter<W: Write> {
+    out: W,
+    format: Format,
+    header_written: bool,
+}
+
+impl<W: Write> FrameWriter<W> {
+    fn new(out: W, format: Format) -> FrameWriter<W> {
+        FrameWriter {
+            out,
+            format,
+            header_written: false,
+        }
+    }
+
+    fn write(&mut self, record: &FrameRecord) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            Format::Jsonl => {
+                serde_json::to_writer(&mut self.out, record)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv => {
+                if !self.header_written {
+                    self.write_csv_header(record)?;
+                    self.header_written = true;
+                }
+                write!(self.out, "{},{}", record.frame, record.time)?;
+                if let Some(t) = record.timing {
+                    write!(
+                        self.out,
+                        ",{},{},{},{}",
+                        t.dft, t.filter, t.decompose, t.finalize
+                    )?;
+                }
+                for n in record.notes {
+                    write!(
+                        self.out,
+                        ",{},{},{},{},{}",
+                        n.active as u8, n.id, n.amplitude_out, n.amplitude_iir2, n.endured
+                    )?;
+                }
+                for bin in record.folded {
+                    write!(self.out, ",{bin}")?;
+                }
+                writeln!(self.out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Columns are fixed by the number of note peaks and frequency bins of the first frame
+    fn write_csv_header(&mut self, record: &FrameRecord) -> io::Result<()> {
+        write!(self.out, "frame,time")?;
+        if record.timing.is_some() {
+            write!(
+                self.out,
+                ",timing_dft,timing_filter,timing_decompose,timing_finalize"
+            )?;
+        }
+        for i in 0..record.notes.len() {
+            write!(
+                self.out,
+                ",note{i}_active,note{i}_id,note{i}_amplitude_out,note{i}_amplitude_iir2,note{i}_endured"
+            )?;
+        }
+        for i in 0..record.folded.len() {
+            write!(self.out, ",folded{i}")?;
+        }
+        writeln!(self.out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Appends the samples of the next hop, nothing at the end of the input
+type HopSource = dyn FnMut(&mut Vec<f32>) -> io::Result<()>;
+
+fn open_input(path: &str) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
+
+/// Read a WAV file as mono samples
+fn read_wav(input: Box<dyn Read>, downmix: Downmix) -> Result<(u32, Vec<f32>), Box<dyn Error>> {
+    let mut reader = hound::WavReader::new(io::BufReader::new(input))?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let mono = downmix.interleaved(&interleaved, channels).collect();
+    Ok((spec.sample_rate, mono))
+}
+
+fn analyze(args: &Args, input: Box<dyn Read>, out: impl Write) -> Result<(), Box<dyn Error>> {
+    // Raw PCM is streamed hop by hop, WAV files are decoded up front
+    let (samplerate, mut next_hop): (u32, Box<HopSource>) = match &args.raw {
+        Some(raw) => {
+            let mut pcm = PcmReader::new(input, raw.format, raw.channels, raw.samplerate);
+            pcm.set_downmix(args.downmix);
+            let hop = args.hop;
+            (
+                raw.samplerate,
+                Box::new(move |out| pcm.read_mono(hop, out).map(|_| ())),
+            )
+        }
+        None => {
+            let (samplerate, samples) = read_wav(input, args.downmix)?;
+            let (hop, mut offset) = (args.hop, 0);
+            (
+                samplerate,
+                Box::new(move |out| {
+                    let end = (offset + hop).min(samples.len());
+                    out.extend_from_slice(&samples[offset..end]);
+                    offset = end;
+                    Ok(())
+                }),
+            )
+        }
+    };
+
+    let mut notefinder = match args.analysis_rate {
+        Some(rate) => Notefinder::with_input_rate(rate, samplerate as i32, args.resample)?,
+        None => Notefinder::try_new(samplerate as i32)?,
+    };
+    if let Some(path) = &args.config {
+        let conf = ColorchordConf::load(path)?;
+        for key in conf.unknown_keys() {
+            eprintln!("warning: unknown key {key} in {path}, ignored");
+        }
+        conf.apply(&mut notefinder)?;
+    }
+
+    let mut writer = FrameWriter::new(out, args.format);
+
+    let mut hop = Vec::with_capacity(args.hop);
+    let mut notes = Vec::new();
+    let mut position = 0;
+    for frame in 0.. {
+        hop.clear();
+        next_hop(&mut hop)?;
+        if hop.is_empty() {
+            break;
+        }
+
+        notefinder.push_samples(&hop);
+        notefinder.process();
+        position += hop.len();
+        notefinder.get_notes_into(&mut notes);
+
+        writer.write(&FrameRecord {
+            frame,
+            time: position as f64 / samplerate as f64,
+            notes: &notes,
+            folded: notefinder.get_folded(),
+            timing: args
+                .timing
+                .then(|| StageTiming::from_timing(notefinder.timing())),
+        })?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Open the input and output named in `args` and analyze
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let input = open_input(&args.input)?;
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    analyze(args, input, BufWriter::new(out))
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    /// One second of a 440 Hz sine at 16 kHz as raw f32le
+    fn sine() -> Vec<u8> {
+        (0..16000)
+            .map(|i| (std::f32::consts::TAU * 440.0 * i as f32 / 16000.0).sin() * 0.5)
+            .flat_map(f32::to_le_bytes)
+            .collect()
+    }
+
+    fn analyze_sine(options: &[&str]) -> String {
+        let mut argv = vec!["--raw", "f32le", "--rate", "16000"];
+        argv.extend_from_slice(options);
+        argv.push("-");
+        let args = args(&argv).unwrap();
+
+        let mut out = Vec::new();
+        analyze(&args, Box::new(io::Cursor::new(sine())), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parses_options() {
+        let parsed = args(&["--hop", "256", "--format", "csv", "--timing", "in.wav"]).unwrap();
+        assert_eq!(parsed.input, "in.wav");
+        assert_eq!(parsed.hop, 256);
+        assert_eq!(parsed.format, Format::Csv);
+        assert!(parsed.timing);
+        assert!(parsed.raw.is_none());
+
+        let parsed = args(&["in.wav"]).unwrap();
+        assert_eq!(
+            (parsed.hop, parsed.format, parsed.timing),
+            (512, Format::Jsonl, false)
+        );
+
+        assert!(args(&[]).is_err());
+        assert!(args(&["--hop", "0", "in.wav"]).is_err());
+        assert!(args(&["--format", "xml", "in.wav"]).is_err());
+    }
+
+    #[test]
+    fn hop_has_to_fit_the_ringbuffer() {
+        let max = (RINGBUFFER_SIZE - 1).to_string();
+        assert_eq!(
+            args(&["--hop", &max, "in.wav"]).unwrap().hop,
+            RINGBUFFER_SIZE - 1
+        );
+
+        let err = args(&["--hop", &RINGBUFFER_SIZE.to_string(), "in.wav"])
+            .err()
+            .unwrap();
+        assert_eq!(err, format!("--hop must be at most {max}"));
+    }
+
+    #[test]
+    fn writes_jsonl() {
+        let out = analyze_sine(&["--hop", "1000"]);
+        let frames: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(frames.len(), 16);
+
+        let last = &frames[15];
+        assert_eq!(last["frame"], 15);
+        assert_eq!(last["time"], 1.0);
+        assert!(last.get("timing").is_none());
+        assert!(!last["folded"].as_array().unwrap().is_empty());
+        assert!(
+            last["notes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|n| n["active"] == true)
+        );
+    }
+
+    #[test]
+    fn writes_csv() {
+        let out = analyze_sine(&["--hop", "4000", "--format", "csv", "--timing"]);
+        let rows: Vec<Vec<&str>> = out.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(rows.len(), 5);
+
+        let header = &rows[0];
+        assert_eq!(
+            header[..6],
+            [
+                "frame",
+                "time",
+                "timing_dft",
+                "timing_filter",
+                "timing_decompose",
+                "timing_finalize"
+            ]
+        );
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+        assert_eq!(rows[4][..2], ["3", "1"]);
+
+        // Stage timings are durations, not clock readings
+        for t in &rows[4][2..6] {
+            let t: f64 = t.parse().unwrap();
+            assert!((0.0..1.0).contains(&t), "{t}");
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Mapping note positions to colors.
+//!
+//! `cc_to_rgb` is colorchord's hue wheel, the other mappings let shows use their own palettes.
+//! `ColorMap` wraps every mapping in a serializable enum so palettes can be stored with a show.
+
+use crate::{Pitch, PitchClass, cc_to_rgb};
+use serde::{Deserialize, Serialize};
+
+/// Turns a note position into a color.
+pub trait ColorMapper {
+    /// Color of a note position (a fraction of an octave), saturation and value are 0 - 1
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3];
+}
+
+/// Blend towards gray and scale brightness the same way `hsv_to_rgb` does
+fn shade(color: [f32; 3], saturation: f32, value: f32) -> [f32; 3] {
+    let color = color.map(|c| c * value);
+    let avg = color.iter().sum::<f32>();
+    color.map(|c| (c * saturation + avg * (1.0 - saturation)).clamp(0.0, 1.0))
+}
+
+/// Colorchord's hue wheel as implemented by `cc_to_rgb`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorchordWheel;
+
+impl ColorMapper for ColorchordWheel {
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3] {
+        cc_to_rgb(note, saturation, value)
+    }
+}
+
+/// Evenly spaced hue wheel, an octave covers the full circle once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HueWheel {
+    /// Hue of note position 0 (0 - 1, 0 is red)
+    pub offset: f32,
+}
+
+impl ColorMapper for HueWheel {
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3] {
+        let h = (note + self.offset).rem_euclid(1.0) * 6.0;
+        let x = 1.0 - (h % 2.0 - 1.0).abs();
+        let color = match h as u32 {
+            0 => [1.0, x, 0.0],
+            1 => [x, 1.0, 0.0],
+            2 => [0.0, 1.0, x],
+            3 => [0.0, x, 1.0],
+            4 => [x, 0.0, 1.0],
+            _ => [1.0, 0.0, x],
+        };
+        shade(color, saturation, value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GradientStop {
+    /// Note position of the stop (0 - 1)
+    pub position: f32,
+    pub color: [f32; 3],
+}
+
+/// User defined palette interpolating between color stops.
+///
+/// The gradient wraps around, positions after the last stop blend back into the first one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    pub stops: Vec<GradientStop>,
+}
+
+impl Gradient {
+    /// Stops are sorted by position
+    pub fn new(mut stops: Vec<GradientStop>) -> Gradient {
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        Gradient { stops }
+    }
+
+    fn color_at(&self, position: f32) -> [f32; 3] {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0.0; 3],
+        };
+
+        // Find the stops on either side, wrapping around the octave
+        let next = self.stops.iter().position(|s| s.position > position);
+        let (a, b, span, offset) = match next {
+            Some(0) => (
+                last,
+                first,
+                first.position + 1.0 - last.position,
+                position + 1.0 - last.position,
+            ),
+            Some(i) => {
+                let (a, b) = (&self.stops[i - 1], &self.stops[i]);
+                (a, b, b.position - a.position, position - a.position)
+            }
+            None => (
+                last,
+                first,
+                first.position + 1.0 - last.position,
+                position - last.position,
+            ),
+        };
+
+        let t = if span > 0.0 {
+            (offset / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        std::array::from_fn(|i| a.color[i] + (b.color[i] - a.color[i]) * t)
+    }
+}
+
+impl ColorMapper for Gradient {
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3] {
+        shade(self.color_at(note.rem_euclid(1.0)), saturation, value)
+    }
+}
+
+/// Perceptually uniform hue wheel in the OKLCH color space.
+///
+/// Every note has the same perceived lightness, colors outside of sRGB are clipped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Oklch {
+    /// Perceived lightness (0 - 1)
+    pub lightness: f32,
+    /// Chroma at full saturation, around 0.15 stays mostly within sRGB
+    pub chroma: f32,
+    /// Hue of note position 0 (0 - 1)
+    pub hue_offset: f32,
+}
+
+impl Default for Oklch {
+    fn default() -> Oklch {
+        Oklch {
+            lightness: 0.75,
+            chroma: 0.15,
+            hue_offset: 0.0,
+        }
+    }
+}
+
+impl ColorMapper for Oklch {
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3] {
+        let hue = (note + self.hue_offset).rem_euclid(1.0) * std::f32::consts::TAU;
+        let chroma = self.chroma * saturation.clamp(0.0, 1.0);
+        let (a, b) = (chroma * hue.cos(), chroma * hue.sin());
+        oklab_to_srgb([self.lightness, a, b]).map(|c| c * value.clamp(0.0, 1.0))
+    }
+}
+
+fn srgb_decode(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_encode(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Convert a gamma encoded sRGB color (0 - 1) to OKLab
+pub(crate) fn srgb_to_oklab(color: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = color.map(|c| srgb_decode(c.clamp(0.0, 1.0)));
+
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// Convert an OKLab color to gamma encoded sRGB, colors outside of sRGB are clipped
+pub(crate) fn oklab_to_srgb([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+    [
+        4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_,
+        -1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_,
+        -0.004_196_086_3 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_,
+    ]
+    .map(|c| srgb_encode(c.clamp(0.0, 1.0)))
+}
+
+/// A fixed color for each of the twelve pitch classes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PitchClassColors {
+    /// Pitch class at note position 0, `A` when the notefinder starts at 55 Hz
+    pub root: PitchClass,
+    /// Colors indexed by `PitchClass::index`, starting at C
+    pub colors: [[f32; 3]; 12],
+}
+
+impl PitchClassColors {
+    /// Use the pitch class of the notefinder base frequency as root
+    pub fn for_base_hz(base_hz: f32, colors: [[f32; 3]; 12]) -> PitchClassColors {
+        PitchClassColors {
+            root: Pitch::from_frequency(base_hz).pitch_class,
+            colors,
+        }
+    }
+}
+
+impl ColorMapper for PitchClassColors {
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3] {
+        let semitone = (note.rem_euclid(1.0) * 12.0).round() as usize;
+        let index = (semitone + self.root.index() as usize) % 12;
+        shade(self.colors[index], saturation, value)
+    }
+}
+
+/// Serializable choice of color mapping.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ColorMap {
+    #[default]
+    Colorchord,
+    HueWheel(HueWheel),
+    Gradient(Gradient),
+    Oklch(Oklch),
+    PitchClasses(PitchClassColors),
+}
+
+impl ColorMapper for ColorMap {
+    fn map(&self, note: f32, saturation: f32, value: f32) -> [f32; 3] {
+        match self {
+            ColorMap::Colorchord => ColorchordWheel.map(note, saturation, value),
+            ColorMap::HueWheel(m) => m.map(note, saturation, value),
+            ColorMap::Gradient(m) => m.map(note, saturation, value),
+            ColorMap::Oklch(m) => m.map(note, saturation, value),
+            ColorMap::PitchClasses(m) => m.map(note, saturation, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for (a, b) in a.iter().zip(&b) {
+            assert!((a - b).abs() < 1e-3, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn hue_wheel_primaries() {
+        let wheel = HueWheel::default();
+        assert_close(wheel.map(0.0, 1.0, 1.0), [1.0, 0.0, 0.0]);
+        assert_close(wheel.map(1.0 / 3.0, 1.0, 1.0), [0.0, 1.0, 0.0]);
+        assert_close(wheel.map(2.0 / 3.0, 1.0, 1.0), [0.0, 0.0, 1.0]);
+        assert_close(wheel.map(1.0, 1.0, 1.0), [1.0, 0.0, 0.0]);
+        assert_close(wheel.map(0.25, 1.0, 0.0), [0.0; 3]);
+
+        let shifted = HueWheel { offset: 1.0 / 3.0 };
+        assert_close(shifted.map(0.0, 1.0, 1.0), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn gradient_interpolates_and_wraps() {
+        let gradient = Gradient::new(vec![
+            GradientStop {
+                position: 0.75,
+                color: [0.0, 0.0, 1.0],
+            },
+            GradientStop {
+                position: 0.25,
+                color: [1.0, 0.0, 0.0],
+            },
+        ]);
+        assert_eq!(gradient.stops[0].position, 0.25);
+
+        assert_close(gradient.map(0.25, 1.0, 1.0), [1.0, 0.0, 0.0]);
+        assert_close(gradient.map(0.5, 1.0, 1.0), [0.5, 0.0, 0.5]);
+        // Halfway between the last stop and the first one of the next octave
+        assert_close(gradient.map(0.0, 1.0, 1.0), [0.5, 0.0, 0.5]);
+        assert_close(gradient.map(0.875, 1.0, 1.0), [0.25, 0.0, 0.75]);
+
+        assert_close(Gradient::default().map(0.5, 1.0, 1.0), [0.0; 3]);
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        assert_close(srgb_to_oklab([1.0, 1.0, 1.0]), [1.0, 0.0, 0.0]);
+        assert_close(srgb_to_oklab([0.0, 0.0, 0.0]), [0.0, 0.0, 0.0]);
+        for color in [[1.0, 0.0, 0.0], [0.2, 0.6, 0.4], [0.9, 0.9, 0.1]] {
+            assert_close(oklab_to_srgb(srgb_to_oklab(color)), color);
+        }
+    }
+
+    #[test]
+    fn oklch_keeps_lightness() {
+        let oklch = Oklch::default();
+        for note in [0.0, 0.3, 0.6, 0.9] {
+            let lightness = srgb_to_oklab(oklch.map(note, 1.0, 1.0))[0];
+            assert!(
+                (lightness - oklch.lightness).abs() < 0.02,
+                "{note}: {lightness}"
+            );
+        }
+        assert_close(oklch.map(0.5, 1.0, 0.0), [0.0; 3]);
+    }
+
+    #[test]
+    fn pitch_class_colors_follow_root() {
+        let colors = std::array::from_fn(|i| [i as f32 / 11.0, 0.0, 0.0]);
+        let map = PitchClassColors::for_base_hz(55.0, colors);
+        assert_eq!(map.root, PitchClass::A);
+
+        assert_close(
+            map.map(0.0, 1.0, 1.0),
+            colors[PitchClass::A.index() as usize],
+        );
+        assert_close(
+            map.map(3.0 / 12.0, 1.0, 1.0),
+            colors[PitchClass::C.index() as usize],
+        );
+        // Rounds to the nearest semitone
+        assert_close(
+            map.map(2.6 / 12.0, 1.0, 1.0),
+            colors[PitchClass::C.index() as usize],
+        );
+    }
+
+    #[test]
+    fn color_map_dispatches() {
+        assert_eq!(
+            ColorMap::Colorchord.map(0.3, 1.0, 1.0),
+            cc_to_rgb(0.3, 1.0, 1.0)
+        );
+        let wheel = HueWheel { offset: 0.1 };
+        assert_eq!(
+            ColorMap::HueWheel(wheel).map(0.3, 0.5, 0.8),
+            wheel.map(0.3, 0.5, 0.8)
+        );
+    }
+}
@@ -0,0 +1,610 @@
+//! Reading and writing colorchord2 `.conf` files.
+//!
+//! Colorchord configuration files consist of `key = value` lines, anything after a `#` is
+//! a comment. Keys understood by the notefinder are collected in `NotefinderOverrides`,
+//! keys used by the output drivers in `OutputOptions`. Keys that only matter to the C
+//! binary (sound and display drivers) are kept as-is so the file can be written back out.
+//! Keys rustchord doesn't know about are kept the same way and listed by
+//! `ColorchordConf::unknown_keys`, so newer or customized files still load.
+
+use crate::{DFTAlgorithm, NoteFinderConfigError, Notefinder, NotefinderConfig};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Keys used by colorchord2 that rustchord doesn't interpret, other unrecognized keys
+/// are reported by `ColorchordConf::unknown_keys`.
+const PASSTHROUGH_KEYS: &[&str] = &[
+    "buffer",
+    "sourcename",
+    "sound_source",
+    "devrecord",
+    "devplay",
+    "channels",
+    "sample_channel",
+    "play",
+    "rec",
+    "wininput",
+    "headless",
+    "set_screenx",
+    "set_screeny",
+    "cpu_autolimit",
+    "cpu_autolimit_interval",
+    "address",
+    "port",
+    "skipfirst",
+    "firstval",
+    "flipgb",
+    "fliprg",
+    "skittlequantity",
+    "snakey",
+];
+
+/// Numeric keys that have to be greater than 0
+const POSITIVE_KEYS: &[&str] = &["base_hz"];
+
+#[derive(Error, Debug)]
+pub enum ConfError {
+    #[error("Line {line}: expected `key = value`")]
+    Syntax { line: usize },
+    #[error("Line {line}: invalid value {value:?} for {key}")]
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+    #[error(
+        "Line {line}: {key} outside valid range ({expected_min} - {expected_max}, found {found})"
+    )]
+    OutsideValidRange {
+        line: usize,
+        key: String,
+        expected_min: f64,
+        expected_max: f64,
+        found: f64,
+    },
+    #[error("Line {line}: {key} has to be greater than 0, found {found}")]
+    NotPositive {
+        line: usize,
+        key: String,
+        found: f64,
+    },
+    #[error(transparent)]
+    Notefinder(#[from] NoteFinderConfigError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A value that can be read from and written to a colorchord configuration file.
+trait ConfValue: Sized {
+    fn parse(value: &str) -> Option<Self>;
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    /// Numeric representation used for range checks
+    fn as_f64(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl ConfValue for i32 {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        Some(*self as f64)
+    }
+}
+
+impl ConfValue for usize {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        Some(*self as f64)
+    }
+}
+
+impl ConfValue for f32 {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        Some(*self as f64)
+    }
+}
+
+/// Colorchord stores flags as integers
+impl ConfValue for bool {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse::<i32>().ok().map(|v| v != 0)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self as i32)
+    }
+}
+
+/// Stored as the `do_progressive_dft` integer
+impl ConfValue for DFTAlgorithm {
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok().and_then(DFTAlgorithm::from_raw)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw())
+    }
+}
+
+/// Comma separated list, used for `outdrivers`
+impl ConfValue for Vec<String> {
+    fn parse(value: &str) -> Option<Self> {
+        Some(
+            value
+                .split(',')
+                .map(|v| v.trim().to_owned())
+                .filter(|v| !v.is_empty())
+                .collect(),
+        )
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.join(", "))
+    }
+}
+
+fn parse_value<T: ConfValue>(
+    line: usize,
+    key: &str,
+    value: &str,
+    range: Option<(f64, f64)>,
+) -> Result<T, ConfError> {
+    let parsed = T::parse(value).ok_or_else(|| ConfError::InvalidValue {
+        line,
+        key: key.to_owned(),
+        value: value.to_owned(),
+    })?;
+
+    if let (Some((min, max)), Some(found)) = (range, parsed.as_f64())
+        && !(min..=max).contains(&found)
+    {
+        return Err(ConfError::OutsideValidRange {
+            line,
+            key: key.to_owned(),
+            expected_min: min,
+            expected_max: max,
+            found,
+        });
+    }
+
+    if POSITIVE_KEYS.contains(&key)
+        && let Some(found) = parsed.as_f64()
+        && (found.is_nan() || found <= 0.0)
+    {
+        return Err(ConfError::NotPositive {
+            line,
+            key: key.to_owned(),
+            found,
+        });
+    }
+
+    Ok(parsed)
+}
+
+macro_rules! conf_section {
+    (
+    $(#[$meta:meta])*
+    $section:ident {
+        $(
+            $(#[$field_meta:meta])*
+            $key:literal => $field:ident: $v:ty $(, $min:expr, $max:expr)?;
+        )*
+    }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct $section {
+            $(
+                $(#[$field_meta])*
+                pub $field: Option<$v>,
+            )*
+        }
+
+        impl $section {
+            /// Parse a value if the key belongs to this section.
+            ///
+            /// Returns `Ok(false)` if the key is unknown to the section.
+            fn set(&mut self, line: usize, key: &str, value: &str) -> Result<bool, ConfError> {
+                match key {
+                    $(
+                        $key => {
+                            let range: Option<(f64, f64)> = None $(.or(Some(($min, $max))))?;
+                            self.$field = Some(parse_value(line, key, value, range)?);
+                        }
+                    )*
+                    _ => return Ok(false),
+                }
+
+                Ok(true)
+            }
+
+            fn write(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                $(
+                    if let Some(v) = &self.$field {
+                        write!(f, "{} = ", $key)?;
+                        v.write(f)?;
+                        writeln!(f)?;
+                    }
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
+conf_section!(
+    /// Notefinder settings present in a configuration file.
+    ///
+    /// Keys use the names registered by colorchord's `notefinder.c`. Values are checked
+    /// against the ranges of the `Notefinder` setters, limits that depend on the samplerate
+    /// are checked by `apply`.
+    NotefinderOverrides {
+        "octaves" => octaves: i32, 1., 8.;
+        "freqbins" => frequency_bins: i32, 12., 48.;
+        "base_hz" => base_hz: f32, 0., 20000.;
+        "filter_strength" => filter_strength: f32, 0., 1.;
+        "filter_iter" => filter_iterations: i32, 1., 8.;
+        "decompose_iterations" => decompose_iterations: i32, 100., 10000.;
+        "amplify" => amplification: f32, 0., 40.;
+        "compress_exponent" => compress_exponent: f32, 0., 10.;
+        "compress_coefficient" => compress_coefficient: f32, 0., 5.;
+        "dft_speedup" => dft_speedup: f32, 100., 20000.;
+        "dft_q" => dft_q: f32, 4., 64.;
+        "default_sigma" => default_sigma: f32, 0., 8.;
+        "note_jumpability" => note_jumpability: f32, 0., 8.;
+        "note_combine_distance" => note_combine_distance: f32, 0., 4.;
+        "slope" => slope: f32, 0., 1.;
+        "note_attach_freq_iir" => note_attach_freq_iir: f32, 0., 3.;
+        "note_attach_amp_iir" => note_attach_amp_iir: f32, 0., 3.;
+        "note_attach_amp_iir2" => note_attach_amp_iir2: f32, 0., 3.;
+        "note_minimum_new_distribution_value" => note_minimum_new_distribution_value: f32, 0., 1.;
+        "note_out_chop" => note_out_chop: f32, 0., 1.;
+        "dft_iir" => dft_iir: f32, 0., 10.;
+        "do_progressive_dft" => dft_algorithm: DFTAlgorithm;
+    }
+);
+
+conf_section!(
+    /// Settings for colorchord's output drivers.
+    OutputOptions {
+        /// Output drivers to chain, for example `DisplayNetwork, OutputLinear`
+        "outdrivers" => outdrivers: Vec<String>;
+        /// Number of LEDs
+        "leds" => leds: usize, 0., 100000.;
+        /// Width of a LED matrix
+        "lightx" => lightx: usize, 0., 10000.;
+        /// Height of a LED matrix
+        "lighty" => lighty: usize, 0., 10000.;
+        "fromsides" => fromsides: bool;
+        "shape_cutoff" => shape_cutoff: f32, 0., 1.;
+        /// Saturation amplification
+        "satamp" => satamp: f32, 0., 100.;
+        "amppow" => amppow: f32, 0., 100.;
+        "distpow" => distpow: f32, 0., 100.;
+        /// Keep LEDs at full brightness regardless of note amplitude
+        "steady_bright" => steady_bright: bool;
+        /// Share of the total note amplitude a note needs before it gets LEDs
+        "led_floor" => led_floor: f32, 0., 1.;
+        /// How much a light bleeds into its neighbours
+        "light_siding" => light_siding: f32, 0., 100.;
+        /// Wrap the LEDs around, for strips arranged in a circle
+        "is_loop" => is_loop: bool;
+        "qtyamp" => qtyamp: f32, 0., 1000.;
+        /// Maximum LED brightness
+        "led_limit" => led_limit: f32, 0., 1.;
+        /// Reassign the LEDs that have been unchanged the longest
+        "timebased" => timebased: bool;
+        /// Fade of the Voronoi cells between frames
+        "decay" => decay: f32, 0., 1.;
+    }
+);
+
+impl NotefinderOverrides {
+    /// Overwrite the values present in the file on top of an existing configuration
+    pub fn merge_into(&self, config: &mut NotefinderConfig) {
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(v) = self.$field {
+                        config.$field = v;
+                    }
+                )*
+            };
+        }
+        merge!(
+            octaves,
+            frequency_bins,
+            base_hz,
+            filter_strength,
+            filter_iterations,
+            decompose_iterations,
+            amplification,
+            compress_exponent,
+            compress_coefficient,
+            dft_speedup,
+            dft_q,
+            default_sigma,
+            note_jumpability,
+            note_combine_distance,
+            slope,
+            note_attach_freq_iir,
+            note_attach_amp_iir,
+            note_attach_amp_iir2,
+            note_minimum_new_distribution_value,
+            note_out_chop,
+            dft_iir,
+            dft_algorithm
+        );
+    }
+}
+
+impl From<&NotefinderConfig> for NotefinderOverrides {
+    fn from(config: &NotefinderConfig) -> NotefinderOverrides {
+        NotefinderOverrides {
+            octaves: Some(config.octaves),
+            frequency_bins: Some(config.frequency_bins),
+            base_hz: Some(config.base_hz),
+            filter_strength: Some(config.filter_strength),
+            filter_iterations: Some(config.filter_iterations),
+            decompose_iterations: Some(config.decompose_iterations),
+            amplification: Some(config.amplification),
+            compress_exponent: Some(config.compress_exponent),
+            compress_coefficient: Some(config.compress_coefficient),
+            dft_speedup: Some(config.dft_speedup),
+            dft_q: Some(config.dft_q),
+            default_sigma: Some(config.default_sigma),
+            note_jumpability: Some(config.note_jumpability),
+            note_combine_distance: Some(config.note_combine_distance),
+            slope: Some(config.slope),
+            note_attach_freq_iir: Some(config.note_attach_freq_iir),
+            note_attach_amp_iir: Some(config.note_attach_amp_iir),
+            note_attach_amp_iir2: Some(config.note_attach_amp_iir2),
+            note_minimum_new_distribution_value: Some(config.note_minimum_new_distribution_value),
+            note_out_chop: Some(config.note_out_chop),
+            dft_iir: Some(config.dft_iir),
+            dft_algorithm: Some(config.dft_algorithm),
+        }
+    }
+}
+
+/// Contents of a colorchord2 configuration file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorchordConf {
+    /// Samplerate colorchord captures audio at
+    pub samplerate: Option<i32>,
+    pub notefinder: NotefinderOverrides,
+    pub output: OutputOptions,
+    /// Keys for colorchord's sound and display drivers and unknown keys, kept verbatim
+    pub passthrough: BTreeMap<String, String>,
+}
+
+impl ColorchordConf {
+    /// Read and parse a configuration file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ColorchordConf, ConfError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Write the configuration to a file in colorchord's format
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfError> {
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Capture the current configuration of a notefinder
+    pub fn from_notefinder(notefinder: &Notefinder) -> ColorchordConf {
+        ColorchordConf {
+            samplerate: Some(notefinder.samplerate()),
+            notefinder: NotefinderOverrides::from(&notefinder.config()),
+            ..Default::default()
+        }
+    }
+
+    /// Keys in `passthrough` that aren't known colorchord2 keys, for example from a newer
+    /// version or a typo. They are written back out but otherwise ignored.
+    pub fn unknown_keys(&self) -> impl Iterator<Item = &str> {
+        self.passthrough
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !PASSTHROUGH_KEYS.contains(key))
+    }
+
+    /// Apply the notefinder settings from the file on top of the current configuration.
+    ///
+    /// Values are validated by `Notefinder::apply_config`, nothing is applied if any of them is invalid.
+    pub fn apply(&self, notefinder: &mut Notefinder) -> Result<(), ConfError> {
+        let mut config = notefinder.config();
+        self.notefinder.merge_into(&mut config);
+        notefinder.apply_config(&config)?;
+        Ok(())
+    }
+}
+
+impl FromStr for ColorchordConf {
+    type Err = ConfError;
+
+    fn from_str(s: &str) -> Result<ColorchordConf, ConfError> {
+        let mut conf = ColorchordConf::default();
+
+        for (index, raw) in s.lines().enumerate() {
+            let line = index + 1;
+            let content = raw.split('#').next().unwrap_or_default().trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            let (key, value) = content.split_once('=').ok_or(ConfError::Syntax { line })?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if key == "samplerate" {
+                conf.samplerate = Some(parse_value(line, key, value, None)?);
+            } else if !conf.notefinder.set(line, key, value)?
+                && !conf.output.set(line, key, value)?
+            {
+                conf.passthrough.insert(key.to_owned(), value.to_owned());
+            }
+        }
+
+        Ok(conf)
+    }
+}
+
+impl fmt::Display for ColorchordConf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(samplerate) = self.samplerate {
+            writeln!(f, "samplerate = {}", samplerate)?;
+        }
+        self.notefinder.write(f)?;
+        self.output.write(f)?;
+        for (key, value) in &self.passthrough {
+            writeln!(f, "{} = {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STOCK: &str = "\
+# Excerpt of colorchord2's default.conf
+outdrivers = DisplayNetwork, OutputLinear
+leds = 296
+lightx = 20
+lighty = 20
+fromsides = 1
+shape_cutoff = 0.03
+satamp = 5.000
+amppow = 2.510
+distpow = 1.500
+address = 192.168.4.1
+port = 7777
+skipfirst = 1
+firstval = 0
+fliprg = 1
+led_limit = 0.8
+timebased = 1
+skittlequantity = 4
+headless = 0
+base_hz = 55.0
+octaves = 6 # trailing comment
+do_progressive_dft = 4
+custom_thing = 3
+";
+
+    #[test]
+    fn parses_stock_config() {
+        let conf: ColorchordConf = STOCK.parse().unwrap();
+
+        assert_eq!(conf.notefinder.octaves, Some(6));
+        assert_eq!(conf.notefinder.base_hz, Some(55.0));
+        assert_eq!(
+            conf.notefinder.dft_algorithm,
+            Some(DFTAlgorithm::DFTProgressive32)
+        );
+        assert_eq!(
+            conf.output.outdrivers.as_deref(),
+            Some(&["DisplayNetwork".to_owned(), "OutputLinear".to_owned()][..])
+        );
+        assert_eq!(conf.output.leds, Some(296));
+        assert_eq!(conf.output.led_limit, Some(0.8));
+        assert_eq!(conf.output.timebased, Some(true));
+        assert_eq!(conf.passthrough["fliprg"], "1");
+        assert_eq!(conf.passthrough["custom_thing"], "3");
+        assert_eq!(conf.unknown_keys().collect::<Vec<_>>(), ["custom_thing"]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let conf: ColorchordConf = STOCK.parse().unwrap();
+        let written: ColorchordConf = conf.to_string().parse().unwrap();
+        assert_eq!(conf, written);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(matches!(
+            "octaves = many".parse::<ColorchordConf>(),
+            Err(ConfError::InvalidValue { line: 1, .. })
+        ));
+        assert!(matches!(
+            "\nsatamp = 500".parse::<ColorchordConf>(),
+            Err(ConfError::OutsideValidRange { line: 2, .. })
+        ));
+        assert!(matches!(
+            "octaves = 50".parse::<ColorchordConf>(),
+            Err(ConfError::OutsideValidRange { line: 1, .. })
+        ));
+        assert!(matches!(
+            "amplify = 2\n\ndft_q = -1".parse::<ColorchordConf>(),
+            Err(ConfError::OutsideValidRange { line: 3, .. })
+        ));
+        assert!(matches!(
+            "base_hz = 0".parse::<ColorchordConf>(),
+            Err(ConfError::NotPositive { line: 1, .. })
+        ));
+        assert!(matches!(
+            "octaves 6".parse::<ColorchordConf>(),
+            Err(ConfError::Syntax { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn merges_overrides() {
+        let conf: ColorchordConf = "amplify = 2.5\nfilter_iter = 3".parse().unwrap();
+        let mut config = NotefinderConfig {
+            octaves: 5,
+            frequency_bins: 24,
+            base_hz: 55.0,
+            filter_strength: 0.5,
+            filter_iterations: 1,
+            decompose_iterations: 1000,
+            amplification: 1.0,
+            compress_exponent: 0.0,
+            compress_coefficient: 1.0,
+            dft_speedup: 300.0,
+            dft_q: 16.0,
+            default_sigma: 1.4,
+            note_jumpability: 2.5,
+            note_combine_distance: 0.5,
+            slope: 0.0,
+            note_attach_freq_iir: 0.3,
+            note_attach_amp_iir: 0.2,
+            note_attach_amp_iir2: 0.05,
+            note_minimum_new_distribution_value: 0.02,
+            note_out_chop: 0.1,
+            dft_iir: 0.0,
+            dft_algorithm: DFTAlgorithm::DFTQuick,
+        };
+        conf.notefinder.merge_into(&mut config);
+
+        assert_eq!(config.amplification, 2.5);
+        assert_eq!(config.filter_iterations, 3);
+        assert_eq!(config.octaves, 5);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{DFTAlgorithm, NoteFinderValidationError, Notefinder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Every tunable parameter of the notefinder in one place.
+///
+/// Read the current values with `Notefinder::config` and apply them with
+/// `Notefinder::apply_config`. Field names match the `set_` methods on `Notefinder`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NotefinderConfig {
+    pub octaves: i32,
+    pub frequency_bins: i32,
+    pub base_hz: f32,
+    pub filter_strength: f32,
+    pub filter_iterations: i32,
+    pub decompose_iterations: i32,
+    pub amplification: f32,
+    pub compress_exponent: f32,
+    pub compress_coefficient: f32,
+    pub dft_speedup: f32,
+    pub dft_q: f32,
+    pub default_sigma: f32,
+    pub note_jumpability: f32,
+    pub note_combine_distance: f32,
+    pub slope: f32,
+    pub note_attach_freq_iir: f32,
+    pub note_attach_amp_iir: f32,
+    pub note_attach_amp_iir2: f32,
+    pub note_minimum_new_distribution_value: f32,
+    pub note_out_chop: f32,
+    pub dft_iir: f32,
+    pub dft_algorithm: DFTAlgorithm,
+}
+
+#[derive(Error, Debug)]
+pub enum NoteFinderConfigError {
+    #[error("Invalid value for {field}: {source}")]
+    Integer {
+        field: &'static str,
+        source: NoteFinderValidationError<i32>,
+    },
+    #[error("Invalid value for {field}: {source}")]
+    Float {
+        field: &'static str,
+        source: NoteFinderValidationError<f32>,
+    },
+}
+
+macro_rules! apply_setting {
+    ($nf:expr, $config:expr, $func_name:ident, $field:ident, $variant:ident) => {
+        $nf.$func_name($config.$field)
+            .map_err(|source| NoteFinderConfigError::$variant {
+                field: stringify!($field),
+                source,
+            })?
+    };
+}
+
+impl Notefinder {
+    /// Read the current configuration of the notefinder
+    pub fn config(&self) -> NotefinderConfig {
+        let nf = self.nf.params();
+        NotefinderConfig {
+            octaves: nf.octaves,
+            frequency_bins: nf.freqbins,
+            base_hz: nf.base_hz,
+            filter_strength: nf.filter_strength,
+            filter_iterations: nf.filter_iter,
+            decompose_iterations: nf.decompose_iterations,
+            amplification: nf.amplify,
+            compress_exponent: nf.compress_exponenet,
+            compress_coefficient: nf.compress_coefficient,
+            dft_speedup: nf.dft_speedup,
+            dft_q: nf.dft_q,
+            default_sigma: nf.default_sigma,
+            note_jumpability: nf.note_jumpability,
+            note_combine_distance: nf.note_combine_distance,
+            slope: nf.slope,
+            note_attach_freq_iir: nf.note_attach_freq_iir,
+            note_attach_amp_iir: nf.note_attach_amp_iir,
+            note_attach_amp_iir2: nf.note_attach_amp_iir2,
+            note_minimum_new_distribution_value: nf.note_minimum_new_distribution_value,
+            note_out_chop: nf.note_out_chop,
+            dft_iir: nf.dft_iir,
+            dft_algorithm: self.dft_algorithm(),
+        }
+    }
+
+    /// Apply a full configuration to the notefinder.
+    ///
+    /// Every value is validated against the same ranges as the `set_` methods. If any
+    /// value is invalid the error is returned and none of the configuration is applied.
+    ///
+    /// The internal buffers are reallocated once if octaves, frequency bins or base Hz changed.
+    pub fn apply_config(&mut self, config: &NotefinderConfig) -> Result<(), NoteFinderConfigError> {
+        let previous = self.nf.params();
+        self.apply_settings(config)
+            .inspect_err(|_| self.nf.set_params(previous))?;
+
+        // Reallocate once after everything else has been validated and applied
+        if previous.octaves != config.octaves
+            || previous.freqbins != config.frequency_bins
+            || previous.base_hz != config.base_hz
+        {
+            self.rebuild();
+        }
+
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, config: &NotefinderConfig) -> Result<(), NoteFinderConfigError> {
+        use NoteFinderConfigError::{Float, Integer};
+
+        self.validate_octaves(config.octaves, config.base_hz)
+            .map_err(|source| Integer {
+                field: "octaves",
+                source,
+            })?;
+        Self::validate_frequency_bins(config.frequency_bins).map_err(|source| Integer {
+            field: "frequency_bins",
+            source,
+        })?;
+        self.validate_base_hz(config.base_hz, config.octaves)
+            .map_err(|source| Float {
+                field: "base_hz",
+                source,
+            })?;
+        let mut params = self.nf.params();
+        params.octaves = config.octaves;
+        params.freqbins = config.frequency_bins;
+        params.base_hz = config.base_hz;
+        self.nf.set_params(params);
+
+        apply_setting!(self, config, set_filter_strength, filter_strength, Float);
+        apply_setting!(
+            self,
+            config,
+            set_filter_iterations,
+            filter_iterations,
+            Integer
+        );
+        apply_setting!(
+            self,
+            config,
+            set_decompose_iterations,
+            decompose_iterations,
+            Integer
+        );
+        apply_setting!(self, config, set_amplification, amplification, Float);
+        apply_setting!(
+            self,
+            config,
+            set_compress_exponent,
+            compress_exponent,
+            Float
+        );
+        apply_setting!(
+            self,
+            config,
+            set_compress_coefficient,
+            compress_coefficient,
+            Float
+        );
+        apply_setting!(self, config, set_dft_speedup, dft_speedup, Float);
+        apply_setting!(self, config, set_dft_q, dft_q, Float);
+        apply_setting!(self, config, set_default_sigma, default_sigma, Float);
+        apply_setting!(self, config, set_note_jumpability, note_jumpability, Float);
+        apply_setting!(
+            self,
+            config,
+            set_note_combine_distance,
+            note_combine_distance,
+            Float
+        );
+        apply_setting!(self, config, set_slope, slope, Float);
+        apply_setting!(
+            self,
+            config,
+            set_note_attach_freq_iir,
+            note_attach_freq_iir,
+            Float
+        );
+        apply_setting!(
+            self,
+            config,
+            set_note_attach_amp_iir,
+            note_attach_amp_iir,
+            Float
+        );
+        apply_setting!(
+            self,
+            config,
+            set_note_attach_amp_iir2,
+            note_attach_amp_iir2,
+            Float
+        );
+        apply_setting!(
+            self,
+            config,
+            set_note_minimum_new_distribution_value,
+            note_minimum_new_distribution_value,
+            Float
+        );
+        apply_setting!(self, config, set_note_out_chop, note_out_chop, Float);
+        apply_setting!(self, config, set_dft_iir, dft_iir, Float);
+        self.set_dft_algorithm(config.dft_algorithm);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_base_hz_of_zero() {
+        let mut notefinder = Notefinder::new(16000);
+        let base_hz = notefinder.base_hz();
+
+        for invalid in [0.0, -55.0, f32::NAN] {
+            assert!(matches!(
+                notefinder.set_base_hz(invalid),
+                Err(NoteFinderValidationError::NotGreaterThan { .. })
+            ));
+
+            let config = NotefinderConfig {
+                base_hz: invalid,
+                ..notefinder.config()
+            };
+            assert!(matches!(
+                notefinder.apply_config(&config),
+                Err(NoteFinderConfigError::Float {
+                    field: "base_hz",
+                    ..
+                })
+            ));
+        }
+        assert_eq!(notefinder.base_hz(), base_hz);
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let mut notefinder = Notefinder::new(16000);
+        let config = NotefinderConfig {
+            base_hz: 110.0,
+            octaves: 4,
+            amplification: 3.0,
+            ..notefinder.config()
+        };
+        notefinder.apply_config(&config).unwrap();
+        assert_eq!(notefinder.config(), config);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{MAX_FREQUENCY_BINS, Note, NoteDists, Notefinder, Timing};
+use serde::{Deserialize, Serialize};
+
+/// Readout of one notefinder run that can be refilled without allocating.
+///
+/// The buffers are sized for the largest number of frequency bins the notefinder accepts,
+/// so `Notefinder::read_frame` never allocates, whatever the settings.
+///
+/// ```no_run
+/// # let mut notefinder = rustchord::Notefinder::new(48000);
+/// let mut frame = rustchord::Frame::new();
+/// loop {
+///     notefinder.process();
+///     notefinder.read_frame(&mut frame);
+///     for note in frame.notes().iter().filter(|n| n.active) {
+///         // ...
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Frame {
+    notes: Vec<Note>,
+    folded: Vec<f32>,
+    timing: Timing,
+}
+
+impl Frame {
+    pub fn new() -> Frame {
+        let bins = MAX_FREQUENCY_BINS as usize;
+        Frame {
+            notes: Vec::with_capacity(bins / 2),
+            folded: Vec::with_capacity(bins),
+            timing: Timing::default(),
+        }
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Folded frequency bins
+    pub fn folded(&self) -> &[f32] {
+        &self.folded
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Frame {
+        Frame::new()
+    }
+}
+
+/// Owned snapshot of everything one notefinder run produced.
+///
+/// Unlike the borrowing getters it stays valid after the next run, so it can be sent to
+/// other threads or recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisFrame {
+    /// Number of the run the snapshot holds, starting at 0, `None` if nothing ran yet
+    pub frame: Option<u64>,
+    /// Seconds of audio analyzed when the snapshot was taken, see `timestamp`
+    pub timestamp: f64,
+    pub notes: Vec<Note>,
+    /// Folded frequency bins, one octave of `frequency_bins`
+    pub folded: Vec<f32>,
+    /// DFT output bins, `frequency_bins * octaves`
+    pub outbins: Vec<f32>,
+    /// Distributions found in the folded bins
+    pub distributions: Vec<NoteDists>,
+    pub timing: Timing,
+}
+
+impl Notefinder {
+    /// Number of times the notefinder has run
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Seconds of audio pushed into the ringbuffer or passed to `run` so far.
+    ///
+    /// Counted at the notefinder samplerate, after resampling. Every sample passed to `run`
+    /// counts, so overlapping windows are counted more than once.
+    pub fn timestamp(&self) -> f64 {
+        (self.buffer.written() + self.run_samples) as f64 / self.samplerate as f64
+    }
+
+    /// Capture the results of the last run in one owned snapshot
+    pub fn analysis_frame(&self) -> AnalysisFrame {
+        AnalysisFrame {
+            frame: self.frames.checked_sub(1),
+            timestamp: self.timestamp(),
+            notes: self.get_notes(),
+            folded: self.get_folded().to_vec(),
+            outbins: self.get_outbins().to_vec(),
+            distributions: self
+                .get_distributions()
+                .iter()
+                .map(NoteDists::from_raw)
+                .collect(),
+            timing: self.timing(),
+        }
+    }
+
+    /// Iterate over the discovered notes without collecting them
+    pub fn notes(&self) -> impl ExactSizeIterator<Item = Note> + '_ {
+        (0..self.note_peaks()).map(move |i| self.note(i))
+    }
+
+    /// Replace the contents of `notes` with the discovered notes.
+    ///
+    /// Only allocates if `notes` can't hold `note_peaks()` notes.
+    pub fn get_notes_into(&self, notes: &mut Vec<Note>) {
+        notes.clear();
+        notes.extend(self.notes());
+    }
+
+    /// Copy the notes, folded bins and timing of the last run into `frame`
+    pub fn read_frame(&self, frame: &mut Frame) {
+        self.get_notes_into(&mut frame.notes);
+        frame.folded.clear();
+        frame.folded.extend_from_slice(self.get_folded());
+        frame.timing = self.timing();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Notefinder that ran over a few seconds of a chord
+    fn notefinder() -> Notefinder {
+        let mut notefinder = Notefinder::new(16000);
+        let mut t = 0;
+        for _ in 0..100 {
+            let block: Vec<f32> = (t..t + 512)
+                .map(|i| {
+                    let t = i as f32 / 16000.0;
+                    (std::f32::consts::TAU * 440.0 * t).sin()
+                        + 0.5 * (std::f32::consts::TAU * 659.3 * t).sin()
+                })
+                .collect();
+            t += 512;
+            notefinder.push_samples(&block);
+            notefinder.process();
+        }
+        notefinder
+    }
+
+    #[test]
+    fn note_readers_agree() {
+        let notefinder = notefinder();
+        let notes = notefinder.get_notes();
+        assert!(notes.iter().any(|n| n.active));
+
+        assert_eq!(notefinder.notes().len(), notes.len());
+        assert_eq!(notefinder.notes().collect::<Vec<_>>(), notes);
+
+        let mut into = vec![notes[0]; 3];
+        notefinder.get_notes_into(&mut into);
+        assert_eq!(into, notes);
+    }
+
+    #[test]
+    fn read_frame_doesnt_reallocate() {
+        let mut notefinder = notefinder();
+        let mut frame = Frame::new();
+        let capacity = (frame.notes.capacity(), frame.folded.capacity());
+        let pointers = (frame.notes.as_ptr(), frame.folded.as_ptr());
+
+        for _ in 0..3 {
+            notefinder.read_frame(&mut frame);
+            assert_eq!(frame.notes(), notefinder.get_notes());
+            assert_eq!(frame.folded(), notefinder.get_folded());
+            assert_eq!((frame.notes.capacity(), frame.folded.capacity()), capacity);
+            assert_eq!((frame.notes.as_ptr(), frame.folded.as_ptr()), pointers);
+
+            notefinder.push_samples(&[0.0; 512]);
+            notefinder.process();
+        }
+    }
+
+    #[test]
+    fn frame_numbers_start_at_0() {
+        let mut notefinder = Notefinder::new(16000);
+        assert_eq!(notefinder.analysis_frame().frame, None);
+
+        notefinder.process();
+        assert_eq!(notefinder.analysis_frame().frame, Some(0));
+        notefinder.run(&[0.0; 512]);
+        assert_eq!(notefinder.analysis_frame().frame, Some(1));
+        assert_eq!(notefinder.frame_count(), 2);
+    }
+
+    #[test]
+    fn timestamp_counts_pushed_and_run_samples() {
+        let mut notefinder = Notefinder::new(16000);
+        assert_eq!(notefinder.analysis_frame().timestamp, 0.0);
+
+        notefinder.push_samples(&[0.0; 8000]);
+        notefinder.process();
+        assert_eq!(notefinder.analysis_frame().timestamp, 0.5);
+
+        notefinder.run(&[0.0; 4000]);
+        assert_eq!(notefinder.analysis_frame().timestamp, 0.75);
+    }
+
+    #[test]
+    fn analysis_frame_is_an_owned_snapshot() {
+        let mut notefinder = notefinder();
+        let snapshot = notefinder.analysis_frame();
+        assert_eq!(snapshot.frame, Some(99));
+        assert_eq!(snapshot.notes, notefinder.get_notes());
+        assert_eq!(snapshot.folded, notefinder.get_folded());
+        assert_eq!(snapshot.outbins, notefinder.get_outbins());
+        assert_eq!(
+            snapshot.distributions.len(),
+            notefinder.get_distributions().len()
+        );
+
+        notefinder.push_samples(&[0.0; 4096]);
+        notefinder.process();
+        assert_ne!(snapshot.folded, notefinder.get_folded());
+        assert_eq!(notefinder.analysis_frame().frame, Some(100));
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn analysis_frame_round_trips_through_json() {
+        let snapshot = notefinder().analysis_frame();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let parsed: AnalysisFrame = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.frame, snapshot.frame);
+        assert_eq!(parsed.timestamp, snapshot.timestamp);
+        assert_eq!(parsed.notes, snapshot.notes);
+        assert_eq!(parsed.folded, snapshot.folded);
+        assert_eq!(parsed.outbins, snapshot.outbins);
+    }
+}
@@ -0,0 +1,444 @@
+//! Converting audio into the mono `f32` samples the notefinder works with, and reading audio
+//! from sources other than an audio backend.
+
+use crate::Notefinder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, ErrorKind, Read};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A sample type that can be converted to the notefinder's `f32` samples.
+pub trait Sample: Copy {
+    /// Convert to the range -1 - 1
+    fn to_f32(self) -> f32;
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Sample for f64 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
+    }
+}
+
+impl Sample for i32 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 2147483648.0
+    }
+}
+
+impl Sample for u16 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 32768.0) / 32768.0
+    }
+}
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 128.0) / 128.0
+    }
+}
+
+/// How multichannel audio is reduced to the single channel the notefinder analyzes.
+///
+/// `Left`, `Right`, `Mid` and `Side` use the first two channels, mono input is used as is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Downmix {
+    /// Average of all channels
+    #[default]
+    Average,
+    Left,
+    Right,
+    /// Sum of left and right, halved
+    Mid,
+    /// Difference of left and right, halved
+    Side,
+}
+
+impl Downmix {
+    /// Downmix interleaved samples with `channels` channels, a trailing partial frame is ignored.
+    ///
+    /// Panics if `channels` is 0.
+    pub fn interleaved<S: Sample>(
+        self,
+        samples: &[S],
+        channels: usize,
+    ) -> impl Iterator<Item = f32> + '_ {
+        assert!(channels > 0, "interleaved input needs at least one channel");
+        samples
+            .chunks_exact(channels)
+            .map(move |frame| self.mix(channels, |ch| frame[ch].to_f32()))
+    }
+
+    /// Mix a frame of `channels` samples, `sample` returns the sample of a channel
+    fn mix(self, channels: usize, sample: impl Fn(usize) -> f32) -> f32 {
+        if channels == 1 {
+            return sample(0);
+        }
+        match self {
+            Downmix::Average => (0..channels).map(&sample).sum::<f32>() / channels as f32,
+            Downmix::Left => sample(0),
+            Downmix::Right => sample(1),
+            Downmix::Mid => (sample(0) + sample(1)) / 2.0,
+            Downmix::Side => (sample(0) - sample(1)) / 2.0,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown downmix {0}, expected average, left, right, mid or side")]
+pub struct UnknownDownmix(pub String);
+
+impl FromStr for Downmix {
+    type Err = UnknownDownmix;
+
+    fn from_str(s: &str) -> Result<Downmix, UnknownDownmix> {
+        match s.to_ascii_lowercase().as_str() {
+            "average" => Ok(Downmix::Average),
+            "left" => Ok(Downmix::Left),
+            "right" => Ok(Downmix::Right),
+            "mid" => Ok(Downmix::Mid),
+            "side" => Ok(Downmix::Side),
+            _ => Err(UnknownDownmix(s.to_string())),
+        }
+    }
+}
+
+impl Notefinder {
+    /// Append mono samples of any `Sample` type to the ringbuffer
+    pub fn push_mono<S: Sample>(&mut self, samples: &[S]) {
+        self.write_samples(samples.iter().map(|s| s.to_f32()));
+    }
+
+    /// Append interleaved samples with `channels` channels to the ringbuffer.
+    ///
+    /// A trailing partial frame is ignored. Panics if `channels` is 0.
+    pub fn push_interleaved<S: Sample>(
+        &mut self,
+        samples: &[S],
+        channels: usize,
+        downmix: Downmix,
+    ) {
+        self.write_samples(downmix.interleaved(samples, channels));
+    }
+
+    /// Append planar samples, one slice per channel, to the ringbuffer.
+    ///
+    /// Only as many frames as the shortest channel holds are used.
+    pub fn push_planar<S: Sample>(&mut self, channels: &[&[S]], downmix: Downmix) {
+        let frames = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        self.write_samples(
+            (0..frames).map(|i| downmix.mix(channels.len(), |ch| channels[ch][i].to_f32())),
+        );
+    }
+}
+
+/// Sample encoding of raw PCM, as produced by `sox -t raw` or `ffmpeg -f s16le`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PcmFormat {
+    /// Signed 16 bit little endian
+    S16Le,
+    /// Signed 32 bit little endian
+    S32Le,
+    /// 32 bit float little endian
+    F32Le,
+    /// Unsigned 8 bit
+    U8,
+}
+
+impl PcmFormat {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            PcmFormat::S16Le => 2,
+            PcmFormat::S32Le | PcmFormat::F32Le => 4,
+            PcmFormat::U8 => 1,
+        }
+    }
+
+    /// Decode a single sample to the range -1 - 1
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            PcmFormat::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]).to_f32(),
+            PcmFormat::S32Le => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_f32()
+            }
+            PcmFormat::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            PcmFormat::U8 => bytes[0].to_f32(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown PCM format {0}, expected s16le, s32le, f32le or u8")]
+pub struct UnknownPcmFormat(pub String);
+
+impl FromStr for PcmFormat {
+    type Err = UnknownPcmFormat;
+
+    fn from_str(s: &str) -> Result<PcmFormat, UnknownPcmFormat> {
+        match s.to_ascii_lowercase().as_str() {
+            "s16le" => Ok(PcmFormat::S16Le),
+            "s32le" => Ok(PcmFormat::S32Le),
+            "f32le" => Ok(PcmFormat::F32Le),
+            "u8" => Ok(PcmFormat::U8),
+            _ => Err(UnknownPcmFormat(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for PcmFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PcmFormat::S16Le => "s16le",
+            PcmFormat::S32Le => "s32le",
+            PcmFormat::F32Le => "f32le",
+            PcmFormat::U8 => "u8",
+        })
+    }
+}
+
+/// Reads interleaved raw PCM from any `Read`, for example stdin or a pipe, and downmixes it to mono.
+///
+/// Channels are averaged unless another `Downmix` is set.
+///
+/// ```no_run
+/// use rustchord::Notefinder;
+/// use rustchord::input::{PcmFormat, PcmReader};
+///
+/// let mut pcm = PcmReader::new(std::io::stdin().lock(), PcmFormat::S16Le, 2, 48000);
+/// let mut notefinder = Notefinder::new(pcm.samplerate() as i32);
+/// while pcm.feed(&mut notefinder, 512)? > 0 {
+///     notefinder.process();
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct PcmReader<R> {
+    reader: R,
+    format: PcmFormat,
+    channels: usize,
+    samplerate: u32,
+    downmix: Downmix,
+    bytes: Vec<u8>,
+    samples: Vec<f32>,
+}
+
+impl<R: Read> PcmReader<R> {
+    /// Panics if `channels` is 0
+    pub fn new(reader: R, format: PcmFormat, channels: usize, samplerate: u32) -> PcmReader<R> {
+        assert!(channels > 0, "PCM input needs at least one channel");
+        PcmReader {
+            reader,
+            format,
+            channels,
+            samplerate,
+            downmix: Downmix::default(),
+            bytes: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn format(&self) -> PcmFormat {
+        self.format
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn samplerate(&self) -> u32 {
+        self.samplerate
+    }
+
+    pub fn downmix(&self) -> Downmix {
+        self.downmix
+    }
+
+    pub fn set_downmix(&mut self, downmix: Downmix) {
+        self.downmix = downmix;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read up to `frames` frames and append them to `out` as mono samples.
+    ///
+    /// Blocks until `frames` frames are available or the input ends, returns the number
+    /// of frames read, 0 at the end of the input.
+    pub fn read_mono(&mut self, frames: usize, out: &mut Vec<f32>) -> io::Result<usize> {
+        let width = self.format.bytes_per_sample();
+        let frame_size = width * self.channels;
+        let wanted = frames * frame_size;
+
+        let mut filled = self.bytes.len();
+        self.bytes.resize(wanted.max(filled), 0);
+        while filled < wanted {
+            match self.reader.read(&mut self.bytes[filled..wanted]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.bytes.truncate(filled);
+                    return Err(err);
+                }
+            }
+        }
+
+        let read = filled / frame_size;
+        let (format, channels, downmix) = (self.format, self.channels, self.downmix);
+        out.extend(
+            self.bytes[..read * frame_size]
+                .chunks_exact(frame_size)
+                .map(|frame| downmix.mix(channels, |ch| format.decode(&frame[ch * width..]))),
+        );
+
+        // Keep a trailing partial frame for the next read
+        self.bytes.truncate(filled);
+        self.bytes.drain(..read * frame_size);
+        Ok(read)
+    }
+
+    /// Read up to `frames` frames and push them into the ringbuffer of the notefinder.
+    ///
+    /// Returns the number of frames read, see `read_mono`.
+    pub fn feed(&mut self, notefinder: &mut Notefinder, frames: usize) -> io::Result<usize> {
+        let mut samples = std::mem::take(&mut self.samples);
+        samples.clear();
+        let read = self.read_mono(frames, &mut samples);
+        notefinder.push_samples(&samples);
+        self.samples = samples;
+        read
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns at most one byte per read, like a slow pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn decodes_pcm_formats() {
+        assert_eq!(PcmFormat::S16Le.decode(&[0x00, 0x80]), -1.0);
+        assert_eq!(PcmFormat::S16Le.decode(&[0x00, 0x40]), 0.5);
+        assert_eq!(PcmFormat::S32Le.decode(&[0, 0, 0, 0xc0]), -0.5);
+        assert_eq!(PcmFormat::F32Le.decode(&0.25f32.to_le_bytes()), 0.25);
+        assert_eq!(PcmFormat::U8.decode(&[128]), 0.0);
+        assert_eq!(PcmFormat::U8.decode(&[0]), -1.0);
+    }
+
+    #[test]
+    fn pcm_format_names_round_trip() {
+        for format in [
+            PcmFormat::S16Le,
+            PcmFormat::S32Le,
+            PcmFormat::F32Le,
+            PcmFormat::U8,
+        ] {
+            assert_eq!(format.to_string().parse::<PcmFormat>().unwrap(), format);
+        }
+        assert!("S16LE".parse::<PcmFormat>().is_ok());
+        assert!("s24le".parse::<PcmFormat>().is_err());
+    }
+
+    #[test]
+    fn reads_frames_across_partial_reads() {
+        let bytes: Vec<u8> = [0x4000i16, 0x2000, -0x4000, 0, 0x1000]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut pcm = PcmReader::new(Trickle(&bytes), PcmFormat::S16Le, 2, 48000);
+
+        let mut out = Vec::new();
+        assert_eq!(pcm.read_mono(1, &mut out).unwrap(), 1);
+        assert_eq!(out, [0.375]);
+        // The trailing half frame is kept until the input ends
+        assert_eq!(pcm.read_mono(4, &mut out).unwrap(), 1);
+        assert_eq!(out, [0.375, -0.25]);
+        assert_eq!(pcm.read_mono(4, &mut out).unwrap(), 0);
+    }
+
+    #[test]
+    fn converts_sample_types() {
+        assert_eq!(i16::MIN.to_f32(), -1.0);
+        assert_eq!(i32::MIN.to_f32(), -1.0);
+        assert_eq!(0u16.to_f32(), -1.0);
+        assert_eq!(32768u16.to_f32(), 0.0);
+        assert_eq!(255u8.to_f32(), 127.0 / 128.0);
+        assert_eq!(0.5f64.to_f32(), 0.5);
+    }
+
+    #[test]
+    fn downmixes_stereo() {
+        let frame = [0.5f32, -0.25];
+        let mix = |downmix: Downmix| downmix.interleaved(&frame, 2).next().unwrap();
+        assert_eq!(mix(Downmix::Average), 0.125);
+        assert_eq!(mix(Downmix::Left), 0.5);
+        assert_eq!(mix(Downmix::Right), -0.25);
+        assert_eq!(mix(Downmix::Mid), 0.125);
+        assert_eq!(mix(Downmix::Side), 0.375);
+    }
+
+    #[test]
+    fn downmix_edge_cases() {
+        // Mono input is used as is, whatever the downmix
+        let mono: Vec<f32> = Downmix::Side.interleaved(&[0.5f32, 0.25], 1).collect();
+        assert_eq!(mono, [0.5, 0.25]);
+
+        // Average uses every channel, the trailing partial frame is dropped
+        let surround = [0.3f32, 0.3, 0.3, 0.0, 0.0, 0.9, 1.0];
+        let mixed: Vec<f32> = Downmix::Average.interleaved(&surround, 3).collect();
+        assert_eq!(mixed.len(), 2);
+        assert!((mixed[0] - 0.3).abs() < 1e-6 && (mixed[1] - 0.3).abs() < 1e-6);
+
+        assert_eq!("MID".parse::<Downmix>().unwrap(), Downmix::Mid);
+        assert!("surround".parse::<Downmix>().is_err());
+    }
+
+    #[test]
+    fn pushes_planar_and_interleaved_alike() {
+        let left = [0i16, 8192, 16384, -16384];
+        let right = [16384i16, 0, -8192, 0];
+        let interleaved: Vec<i16> = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(l, r)| [*l, *r])
+            .collect();
+
+        let mut a = Notefinder::new(16000);
+        a.push_interleaved(&interleaved, 2, Downmix::Mid);
+        let mut b = Notefinder::new(16000);
+        b.push_planar(&[&left, &right], Downmix::Mid);
+        assert_eq!(a.buffer.written(), 4);
+        assert_eq!(b.buffer.written(), 4);
+        assert_eq!(a.buffer.as_slice(), b.buffer.as_slice());
+        assert_eq!(a.buffer.as_slice()[..2], [0.25, 0.125]);
+    }
+}
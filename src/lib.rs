@@ -1,12 +1,32 @@
-use std::slice;
+mod backend;
+pub mod color;
+pub mod conf;
+mod config;
+mod frame;
+pub mod input;
+#[cfg(not(feature = "pure-rust"))]
 mod internal;
-
+pub mod output;
+mod pitch;
+mod resample;
+mod ringbuffer;
+pub mod sink;
+mod tracker;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use config::{NoteFinderConfigError, NotefinderConfig};
+pub use frame::{AnalysisFrame, Frame};
+pub use pitch::{A4_HZ, Pitch, PitchClass, midi_to_frequency};
+pub use resample::{ResampleQuality, Resampler};
+pub use ringbuffer::RINGBUFFER_SIZE;
+pub use tracker::{NoteEvent, NoteTracker, TrackedNote};
 
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use thiserror::Error;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct NoteDists {
     /// Amplitude of normal distribution
     pub amp: f32,
@@ -18,7 +38,18 @@ pub struct NoteDists {
     pub taken: bool,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+impl NoteDists {
+    fn from_raw(dist: &backend::NoteDists) -> NoteDists {
+        NoteDists {
+            amp: dist.amp,
+            mean: dist.mean,
+            sigma: dist.sigma,
+            taken: dist.taken != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Note {
     pub active: bool,
     pub id: f32,
@@ -28,8 +59,8 @@ pub struct Note {
     pub endured: i32,
 }
 
-/// Profiling timers from the internal C pipeline.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Profiling timers from the notefinder pipeline.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct Timing {
     pub start: f64,
     pub dft: f64,
@@ -46,30 +77,64 @@ pub enum NoteFinderValidationError<T: Debug> {
         expected_max: T,
         found: T,
     },
+    #[error("Has to be greater than {exclusive_min:?}, found {found:?}")]
+    NotGreaterThan { exclusive_min: T, found: T },
     #[error("unknown notefinder error")]
     Unknown,
 }
 
+/// Lowest samplerate accepted by `Notefinder::try_new`
+pub const MIN_SAMPLE_RATE: i32 = 8000;
+/// Highest samplerate accepted by `Notefinder::try_new`
+pub const MAX_SAMPLE_RATE: i32 = 192000;
+/// Highest number of frequency bins accepted by `Notefinder::set_frequency_bins`
+pub const MAX_FREQUENCY_BINS: i32 = 48;
+
+#[derive(Error, Debug)]
+pub enum NoteFinderError {
+    #[error("Failed to allocate the notefinder")]
+    AllocationFailed,
+    #[error("Unsupported sample rate ({expected_min} - {expected_max}, found {found})")]
+    UnsupportedSampleRate {
+        expected_min: i32,
+        expected_max: i32,
+        found: i32,
+    },
+}
+
+fn check_range<T: PartialOrd + Copy + Debug>(
+    value: T,
+    min: T,
+    max: T,
+) -> Result<(), NoteFinderValidationError<T>> {
+    if !(min..=max).contains(&value) {
+        return Err(NoteFinderValidationError::OutsideValidRange {
+            expected_min: min,
+            expected_max: max,
+            found: value,
+        });
+    }
+
+    Ok(())
+}
+
 macro_rules! notefinder_configuration {
     (
     $(#[$meta:meta])*
     $func_name:ident, $setting:ident, $v:ty, $name:ident, $min:expr, $max:expr) => {
         $(#[$meta])*
         pub fn $func_name(&self, $name: $v) -> Result<(), NoteFinderValidationError<$v>> {
-            if $name < $min || $name > $max {
-                return Err(NoteFinderValidationError::OutsideValidRange {
-                    expected_min: $min,
-                    expected_max: $max,
-                    found: $name,
-                });
-            }
-
-            unsafe { (*self.nf).$setting = $name }
+            check_range($name, $min, $max)?;
+
+            let mut params = self.nf.params();
+            params.$setting = $name;
+            self.nf.set_params(params);
             Ok(())
         }
     };
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DFTAlgorithm {
     /// Fastest algorithm, results are worse. Useful on low end hardware
     DFTQuick,
@@ -83,190 +148,294 @@ pub enum DFTAlgorithm {
     DFTProgressive32,
 }
 
-pub struct Notefinder {
-    nf: *mut internal::NoteFinder,
-}
+impl DFTAlgorithm {
+    fn from_raw(algo: i32) -> Option<DFTAlgorithm> {
+        use DFTAlgorithm::*;
+        match algo {
+            0 => Some(DFTQuick),
+            1 => Some(DFTProgressive),
+            2 => Some(DFTProgressiveInteger),
+            3 => Some(DFTProgressiveIntegerSkippy),
+            4 => Some(DFTProgressive32),
+            _ => None,
+        }
+    }
 
-// SAFETY: The underlying C NoteFinder state is not thread-safe.
-// Send is safe because ownership can transfer between threads.
-// Sync is intentionally NOT implemented — concurrent access from
-// multiple threads would race on the C state.
-unsafe impl Send for Notefinder {}
-
-impl Drop for Notefinder {
-    fn drop(&mut self) {
-        unsafe {
-            let nf = &mut *self.nf;
-            libc::free(nf.note_positions as *mut libc::c_void);
-            libc::free(nf.note_amplitudes as *mut libc::c_void);
-            libc::free(nf.note_amplitudes_out as *mut libc::c_void);
-            libc::free(nf.note_amplitudes2 as *mut libc::c_void);
-            libc::free(nf.note_founds as *mut libc::c_void);
-            libc::free(nf.note_peaks_to_dists_mapping as *mut libc::c_void);
-            libc::free(nf.enduring_note_id as *mut libc::c_void);
-            libc::free(nf.frequencies as *mut libc::c_void);
-            libc::free(nf.outbins as *mut libc::c_void);
-            libc::free(nf.folded_bins as *mut libc::c_void);
-            libc::free(nf.dists as *mut libc::c_void);
-            libc::free(self.nf as *mut libc::c_void);
+    fn raw(self) -> i32 {
+        use DFTAlgorithm::*;
+        match self {
+            DFTQuick => 0,
+            DFTProgressive => 1,
+            DFTProgressiveInteger => 2,
+            DFTProgressiveIntegerSkippy => 3,
+            DFTProgressive32 => 4,
         }
     }
 }
 
+pub struct Notefinder {
+    nf: backend::NoteFinder,
+    samplerate: i32,
+    buffer: ringbuffer::Ringbuffer,
+    resampler: Option<resample::Resampler>,
+    resampled: Vec<f32>,
+    /// Number of runs so far
+    frames: u64,
+    /// Samples passed to `run`, they don't go through the ringbuffer
+    run_samples: u64,
+}
+
 impl Notefinder {
     /// Create a new instance of the Notefinder with the desired samplerate.
     ///
     /// Samplerate can only be set during creation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the notefinder can't be created, see `try_new` for the fallible version.
     pub fn new(samplerate: i32) -> Notefinder {
-        Notefinder {
-            nf: unsafe { internal::CreateNoteFinder(samplerate) },
+        match Notefinder::try_new(samplerate) {
+            Ok(nf) => nf,
+            Err(e) => panic!("Failed to create notefinder: {}", e),
+        }
+    }
+
+    /// Create a new instance of the Notefinder with the desired samplerate.
+    ///
+    /// The samplerate has to be within `MIN_SAMPLE_RATE` and `MAX_SAMPLE_RATE`.
+    pub fn try_new(samplerate: i32) -> Result<Notefinder, NoteFinderError> {
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&samplerate) {
+            return Err(NoteFinderError::UnsupportedSampleRate {
+                expected_min: MIN_SAMPLE_RATE,
+                expected_max: MAX_SAMPLE_RATE,
+                found: samplerate,
+            });
         }
+
+        let nf = backend::NoteFinder::new(samplerate).ok_or(NoteFinderError::AllocationFailed)?;
+
+        Ok(Notefinder {
+            nf,
+            samplerate,
+            buffer: ringbuffer::Ringbuffer::new(RINGBUFFER_SIZE),
+            resampler: None,
+            resampled: Vec::new(),
+            frames: 0,
+            run_samples: 0,
+        })
     }
 
     /// Run the notefinder over the provided buffer
+    ///
+    /// The buffer is treated as a linear window with its head at 0. Prefer
+    /// `push_samples` and `process` which keep track of the head for you.
+    ///
+    /// `data` must already be at the notefinder samplerate, it doesn't go through the
+    /// resampler set up with `set_input_rate`.
     pub fn run(&mut self, data: &[f32]) {
-        unsafe {
-            internal::RunNoteFinder(self.nf, data.as_ptr(), 0, data.len() as i32);
+        self.nf.run(data, 0);
+        self.frames += 1;
+        self.run_samples += data.len() as u64;
+    }
+
+    /// Append samples to the internal ringbuffer.
+    ///
+    /// The ringbuffer holds `RINGBUFFER_SIZE` samples. At most `RINGBUFFER_SIZE - 1` new
+    /// samples are analyzed by the next `process`, pushing more than that between calls
+    /// drops the oldest ones. Samples are resampled first when an input rate is set with
+    /// `set_input_rate`.
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        if self.resampler.is_some() {
+            self.write_samples(samples.iter().copied());
+        } else {
+            self.buffer.push(samples);
         }
     }
 
+    /// Run the notefinder over the samples pushed since the last call
+    pub fn process(&mut self) {
+        self.nf.run(self.buffer.as_slice(), self.buffer.head());
+        self.buffer.mark_read();
+        self.frames += 1;
+    }
+
     /// Get the discovered notes
+    ///
+    /// Allocates a new `Vec` on every call, see `notes`, `get_notes_into` and `Frame`
+    /// for reading notes without allocating.
     pub fn get_notes(&self) -> Vec<Note> {
-        unsafe {
-            let nf = &*self.nf;
-            let note_peaks = nf.note_peaks as usize;
-            let freqbins = nf.freqbins as f32;
-            let positions = slice::from_raw_parts(nf.note_positions, note_peaks);
-            let amps_out = slice::from_raw_parts(nf.note_amplitudes_out, note_peaks);
-            let amps2 = slice::from_raw_parts(nf.note_amplitudes2, note_peaks);
-            let enduring = slice::from_raw_parts(nf.enduring_note_id, note_peaks);
-            let dists = slice::from_raw_parts(nf.dists, note_peaks);
-
-            (0..note_peaks)
-                .map(|i| Note {
-                    active: amps_out[i] > 0.0,
-                    id: positions[i] / freqbins,
-                    dist: NoteDists {
-                        amp: dists[i].amp,
-                        mean: dists[i].mean,
-                        sigma: dists[i].sigma,
-                        taken: dists[i].taken != 0,
-                    },
-                    amplitude_out: amps_out[i],
-                    amplitude_iir2: amps2[i],
-                    endured: enduring[i],
-                })
-                .collect()
+        self.notes().collect()
+    }
+
+    /// Note `i` of the note peaks
+    fn note(&self, i: usize) -> Note {
+        let amplitude_out = self.nf.note_amplitudes_out()[i];
+        Note {
+            active: amplitude_out > 0.0,
+            id: self.nf.note_positions()[i] / self.frequency_bins() as f32,
+            dist: NoteDists::from_raw(&self.nf.note_dists()[i]),
+            amplitude_out,
+            amplitude_iir2: self.nf.note_amplitudes2()[i],
+            endured: self.nf.enduring_note_id()[i],
         }
     }
 
     /// Get the folded frequency bins
     pub fn get_folded(&self) -> &[f32] {
-        unsafe { slice::from_raw_parts((*self.nf).folded_bins, (*self.nf).freqbins as usize) }
+        self.nf.folded_bins()
     }
 
     /// Get the raw DFT output bins (length = freqbins * octaves)
     pub fn get_outbins(&self) -> &[f32] {
-        unsafe {
-            let nf = &*self.nf;
-            slice::from_raw_parts(nf.outbins, (nf.freqbins * nf.octaves) as usize)
-        }
+        self.nf.outbins()
     }
 
     /// Get the frequency array (length = freqbins * octaves)
     pub fn get_frequencies(&self) -> &[f32] {
-        unsafe {
-            let nf = &*self.nf;
-            slice::from_raw_parts(nf.frequencies, (nf.freqbins * nf.octaves) as usize)
-        }
+        self.nf.frequencies()
     }
 
     /// Get the raw distribution data
-    pub fn get_distributions(&self) -> &[internal::NoteDists] {
-        unsafe {
-            let nf = &*self.nf;
-            slice::from_raw_parts(nf.dists, nf.dists_count as usize)
-        }
+    pub fn get_distributions(&self) -> &[backend::NoteDists] {
+        self.nf.dists()
     }
 
     /// Number of note peaks tracked
     pub fn note_peaks(&self) -> usize {
-        unsafe { (*self.nf).note_peaks as usize }
+        self.nf.note_peaks()
     }
 
     /// Number of frequency bins per octave
     pub fn frequency_bins(&self) -> i32 {
-        unsafe { (*self.nf).freqbins }
+        self.nf.params().freqbins
     }
 
     /// Number of octaves
     pub fn octaves(&self) -> i32 {
-        unsafe { (*self.nf).octaves }
+        self.nf.params().octaves
     }
 
     /// Reciprocal of sample rate
     pub fn sample_rate(&self) -> f32 {
-        unsafe { (*self.nf).sps_rec }
+        self.nf.sps_rec()
+    }
+
+    /// Samplerate the notefinder was created with
+    pub fn samplerate(&self) -> i32 {
+        self.samplerate
+    }
+
+    /// Base frequency of the lowest bin in Hz
+    pub fn base_hz(&self) -> f32 {
+        self.nf.params().base_hz
     }
 
     /// Get internal profiling timers from the last `run()` call
     pub fn timing(&self) -> Timing {
-        unsafe {
-            let nf = &*self.nf;
-            Timing {
-                start: nf.StartTime,
-                dft: nf.DFTTime,
-                filter: nf.FilterTime,
-                decompose: nf.DecomposeTime,
-                finalize: nf.FinalizeTime,
-            }
-        }
+        self.nf.timing()
     }
 
     /// Use this to change the Discrete Fourier transform algorithm.
     ///
     /// Options defined in DFTAlgorithm
     pub fn set_dft_algorithm(&mut self, algo: DFTAlgorithm) {
-        use DFTAlgorithm::*;
-        let dftalgo = match algo {
-            DFTQuick => 0,
-            DFTProgressive => 1,
-            DFTProgressiveInteger => 2,
-            DFTProgressiveIntegerSkippy => 3,
-            DFTProgressive32 => 4,
-        };
-        unsafe { (*self.nf).do_progressive_dft = dftalgo }
+        let mut params = self.nf.params();
+        params.do_progressive_dft = algo.raw();
+        self.nf.set_params(params);
     }
-    notefinder_configuration!(
-        /// Sets the span of octaves
-        /// Defaults to 8
-        set_octaves,
-        octaves,
-        i32,
-        octaves,
-        0,
-        8
-    );
-    notefinder_configuration!(
-        /// Defines the number of frequency bins
-        /// Defaults to 24
-        set_frequency_bins,
-        freqbins,
-        i32,
-        frequency_bins,
-        12,
-        48
-    );
-    notefinder_configuration!(
-        /// Set the base Hz for the notefinder to start at
-        /// Defaults to 0
-        set_base_hz,
-        base_hz,
-        f32,
-        base_hz,
-        0.,
-        20000.
-    );
+
+    /// Currently selected Discrete Fourier transform algorithm
+    pub fn dft_algorithm(&self) -> DFTAlgorithm {
+        DFTAlgorithm::from_raw(self.nf.params().do_progressive_dft)
+            .unwrap_or(DFTAlgorithm::DFTProgressive)
+    }
+
+    /// Sets the span of octaves
+    /// Defaults to 8
+    ///
+    /// Reallocates the internal buffers. The highest octave has to stay below the
+    /// nyquist frequency of the samplerate.
+    pub fn set_octaves(&mut self, octaves: i32) -> Result<(), NoteFinderValidationError<i32>> {
+        self.validate_octaves(octaves, self.base_hz())?;
+
+        let mut params = self.nf.params();
+        params.octaves = octaves;
+        self.nf.set_params(params);
+        self.rebuild();
+        Ok(())
+    }
+
+    /// Defines the number of frequency bins
+    /// Defaults to 24
+    ///
+    /// Reallocates the internal buffers.
+    pub fn set_frequency_bins(
+        &mut self,
+        frequency_bins: i32,
+    ) -> Result<(), NoteFinderValidationError<i32>> {
+        Self::validate_frequency_bins(frequency_bins)?;
+
+        let mut params = self.nf.params();
+        params.freqbins = frequency_bins;
+        self.nf.set_params(params);
+        self.rebuild();
+        Ok(())
+    }
+
+    /// Set the base Hz for the notefinder to start at
+    /// Defaults to 55
+    ///
+    /// Recalculates the frequency table. Has to be greater than 0 and the highest octave
+    /// has to stay below the nyquist frequency of the samplerate.
+    pub fn set_base_hz(&mut self, base_hz: f32) -> Result<(), NoteFinderValidationError<f32>> {
+        self.validate_base_hz(base_hz, self.octaves())?;
+
+        let mut params = self.nf.params();
+        params.base_hz = base_hz;
+        self.nf.set_params(params);
+        self.rebuild();
+        Ok(())
+    }
+
+    fn validate_octaves(
+        &self,
+        octaves: i32,
+        base_hz: f32,
+    ) -> Result<(), NoteFinderValidationError<i32>> {
+        let nyquist = self.samplerate as f32 / 2.0;
+        let max = (nyquist / base_hz).log2().floor().min(8.0) as i32;
+        check_range(octaves, 1, max)
+    }
+
+    fn validate_frequency_bins(frequency_bins: i32) -> Result<(), NoteFinderValidationError<i32>> {
+        check_range(frequency_bins, 12, MAX_FREQUENCY_BINS)
+    }
+
+    fn validate_base_hz(
+        &self,
+        base_hz: f32,
+        octaves: i32,
+    ) -> Result<(), NoteFinderValidationError<f32>> {
+        let nyquist = self.samplerate as f32 / 2.0;
+        let max = (nyquist / 2f32.powi(octaves)).min(20000.);
+        // The DFT periods are derived from base_hz, 0 Hz would be an infinite period
+        if base_hz.is_nan() || base_hz <= 0.0 {
+            return Err(NoteFinderValidationError::NotGreaterThan {
+                exclusive_min: 0.,
+                found: base_hz,
+            });
+        }
+        check_range(base_hz, 0., max)
+    }
+
+    /// Reallocate the internal buffers and frequency table after a structural setting changed.
+    ///
+    /// Colorchord uses the same function when these parameters change at runtime, other
+    /// parameters are left untouched.
+    fn rebuild(&mut self) {
+        self.nf.rebuild()
+    }
+
     notefinder_configuration!(
         /// Controls the strength of the filter
         /// Defaults to 0.5
@@ -0,0 +1,250 @@
+use super::{OutputMapper, floored_amplitudes, to_rgb8};
+use crate::Note;
+use crate::color::{ColorMap, ColorMapper};
+use crate::conf::OutputOptions;
+use serde::{Deserialize, Serialize};
+
+/// Options for `CellsOutput`, defaults match colorchord's `OutputCells`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CellsOptions {
+    /// Keep LEDs at full brightness instead of following the note amplitude
+    pub steady_bright: bool,
+    /// Amplification of the note amplitude before it's used as brightness
+    pub satamp: f32,
+    /// Number of LEDs a note wants per unit of amplitude
+    pub qtyamp: f32,
+    /// Exponent applied to note amplitudes, higher values give loud notes more LEDs
+    pub light_siding: f32,
+    /// Share of the total amplitude subtracted from every note, notes left with a smaller
+    /// share get no LEDs
+    pub led_floor: f32,
+    /// Reassign the LEDs that have been unchanged the longest instead of the first ones found.
+    /// Useful for fixtures where LED order doesn't matter, like pies.
+    pub timebased: bool,
+}
+
+impl Default for CellsOptions {
+    fn default() -> CellsOptions {
+        CellsOptions {
+            steady_bright: false,
+            satamp: 2.0,
+            qtyamp: 20.0,
+            light_siding: 1.9,
+            led_floor: 0.1,
+            timebased: false,
+        }
+    }
+}
+
+impl From<&OutputOptions> for CellsOptions {
+    fn from(conf: &OutputOptions) -> CellsOptions {
+        let defaults = CellsOptions::default();
+        CellsOptions {
+            steady_bright: conf.steady_bright.unwrap_or(defaults.steady_bright),
+            satamp: conf.satamp.unwrap_or(defaults.satamp),
+            qtyamp: conf.qtyamp.unwrap_or(defaults.qtyamp),
+            light_siding: conf.light_siding.unwrap_or(defaults.light_siding),
+            led_floor: conf.led_floor.unwrap_or(defaults.led_floor),
+            timebased: conf.timebased.unwrap_or(defaults.timebased),
+        }
+    }
+}
+
+/// Assigns individual LEDs to notes and only moves them when a note needs more or fewer.
+///
+/// LEDs stay attached to a note between frames, so cells don't flicker between notes.
+pub struct CellsOutput {
+    options: CellsOptions,
+    color_map: ColorMap,
+    frame: u64,
+    /// Note index every LED is attached to
+    attached: Vec<Option<usize>>,
+    /// Frame every LED last changed note
+    changed: Vec<u64>,
+}
+
+impl CellsOutput {
+    pub fn new(leds: usize, options: CellsOptions) -> CellsOutput {
+        CellsOutput {
+            options,
+            color_map: ColorMap::default(),
+            frame: 0,
+            attached: vec![None; leds],
+            changed: vec![0; leds],
+        }
+    }
+
+    /// Create an output using the `leds` count and cells options from a colorchord configuration
+    pub fn from_conf(conf: &OutputOptions) -> CellsOutput {
+        CellsOutput::new(conf.leds.unwrap_or(300), conf.into())
+    }
+
+    pub fn options(&self) -> &CellsOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut CellsOptions {
+        &mut self.options
+    }
+
+    /// Color mapping used for notes, colorchord's hue wheel by default
+    pub fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    pub fn set_color_map(&mut self, color_map: ColorMap) {
+        self.color_map = color_map;
+    }
+
+    /// Pick a LED attached to `note`, either the one changed most recently or the one unchanged the longest
+    fn select(&self, note: Option<usize>, newest: bool) -> Option<usize> {
+        let mut candidates = (0..self.attached.len()).filter(|&i| self.attached[i] == note);
+        if !self.options.timebased {
+            candidates.next()
+        } else if newest {
+            candidates.max_by_key(|&i| self.changed[i])
+        } else {
+            candidates.min_by_key(|&i| self.changed[i])
+        }
+    }
+
+    fn attach(&mut self, led: usize, note: Option<usize>) {
+        self.attached[led] = note;
+        self.changed[led] = self.frame;
+    }
+}
+
+impl OutputMapper for CellsOutput {
+    fn leds(&self) -> usize {
+        self.attached.len()
+    }
+
+    fn update(&mut self, notes: &[Note]) -> Vec<[u8; 3]> {
+        let opts = self.options;
+        let leds = self.attached.len();
+        self.frame += 1;
+
+        // Notes can disappear when the notefinder is reconfigured
+        for attached in self.attached.iter_mut() {
+            if attached.is_some_and(|n| n >= notes.len()) {
+                *attached = None;
+            }
+        }
+
+        let amps = floored_amplitudes(notes, opts.light_siding, opts.led_floor);
+
+        // Step 1: How many LEDs every note wants compared to what it has
+        let mut want: Vec<f32> = amps.iter().map(|a| a.0 * opts.qtyamp).collect();
+        let total_want: f32 = want.iter().sum();
+        if total_want > leds as f32 {
+            let overage = leds as f32 / total_want;
+            want.iter_mut().for_each(|w| *w *= overage);
+        }
+        let mut diff = want;
+        for note in self.attached.iter().flatten() {
+            diff[*note] -= 1.0;
+        }
+
+        // Step 2: Relinquish LEDs from notes that have too many
+        for (note, d) in diff.iter_mut().enumerate() {
+            while *d < -0.5 {
+                if let Some(led) = self.select(Some(note), true) {
+                    self.attach(led, None);
+                }
+                *d += 1.0;
+            }
+        }
+
+        // Step 3: Hand free LEDs to notes that want more
+        for (note, d) in diff.iter_mut().enumerate() {
+            while *d > 0.5 {
+                if let Some(led) = self.select(None, false) {
+                    self.attach(led, Some(note));
+                }
+                *d -= 1.0;
+            }
+        }
+
+        self.attached
+            .iter()
+            .map(|attached| match attached {
+                Some(note) => {
+                    let (slow, fast) = amps[*note];
+                    let sat = (slow * opts.satamp).max(fast * opts.satamp).min(1.0);
+                    let value = if opts.steady_bright { 1.0 } else { sat };
+                    to_rgb8(self.color_map.map(notes[*note].id, 1.0, value))
+                }
+                None => [0; 3],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::note;
+    use super::*;
+
+    /// One LED per unit of amplitude
+    fn plain(timebased: bool) -> CellsOptions {
+        CellsOptions {
+            qtyamp: 1.0,
+            light_siding: 1.0,
+            led_floor: 0.0,
+            timebased,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn leds_stay_with_their_note() {
+        let mut cells = CellsOutput::new(4, plain(false));
+        cells.update(&[note(0.0, 2.0)]);
+        assert_eq!(cells.attached, [Some(0), Some(0), None, None]);
+
+        cells.update(&[note(0.0, 2.0), note(0.5, 2.0)]);
+        assert_eq!(cells.attached, [Some(0), Some(0), Some(1), Some(1)]);
+
+        // Unchanged notes keep their LEDs, a quieter note only gives up what it lost
+        cells.update(&[note(0.0, 2.0), note(0.5, 2.0)]);
+        assert_eq!(cells.attached, [Some(0), Some(0), Some(1), Some(1)]);
+        cells.update(&[note(0.0, 1.0), note(0.5, 2.0)]);
+        assert_eq!(cells.attached, [None, Some(0), Some(1), Some(1)]);
+
+        let leds = cells.update(&[note(0.0, 1.0), note(0.5, 2.0)]);
+        assert_eq!(leds[0], [0; 3]);
+        assert_ne!(leds[1], leds[2]);
+        assert_eq!(leds[2], leds[3]);
+    }
+
+    #[test]
+    fn timebased_moves_the_oldest_leds() {
+        let frames = [
+            vec![note(0.0, 2.0)],
+            vec![note(0.0, 1.0)],
+            vec![note(0.0, 1.0), note(0.5, 1.0)],
+        ];
+
+        let mut cells = CellsOutput::new(4, plain(true));
+        for notes in &frames {
+            cells.update(notes);
+        }
+        // The LED freed last is left alone, the note takes one that has been dark longer
+        assert_eq!(cells.attached, [Some(0), None, Some(1), None]);
+
+        let mut cells = CellsOutput::new(4, plain(false));
+        for notes in &frames {
+            cells.update(notes);
+        }
+        assert_eq!(cells.attached, [Some(1), Some(0), None, None]);
+    }
+
+    #[test]
+    fn shares_leds_when_notes_want_too_many() {
+        let mut cells = CellsOutput::new(4, plain(false));
+        cells.update(&[note(0.0, 6.0), note(0.5, 2.0)]);
+        let first = cells.attached.iter().filter(|a| **a == Some(0)).count();
+        let second = cells.attached.iter().filter(|a| **a == Some(1)).count();
+        assert_eq!((first, second), (3, 1));
+    }
+}
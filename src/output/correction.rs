@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+
+/// Current budget for a LED fixture.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PowerLimit {
+    /// Maximum current the power supply can deliver for the fixture
+    pub max_milliamps: f32,
+    /// Current drawn by a single channel at full brightness, 20 mA for WS2812
+    pub milliamps_per_channel: f32,
+    /// Current drawn by every LED while dark
+    pub idle_milliamps_per_led: f32,
+}
+
+impl Default for PowerLimit {
+    fn default() -> PowerLimit {
+        PowerLimit {
+            max_milliamps: 2000.0,
+            milliamps_per_channel: 20.0,
+            idle_milliamps_per_led: 1.0,
+        }
+    }
+}
+
+/// Options for `LedCorrection`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CorrectionOptions {
+    /// Gamma of the LEDs, 1.0 disables gamma correction
+    pub gamma: f32,
+    /// Multiplier for the red, green and blue channels
+    pub white_balance: [f32; 3],
+    /// Global brightness (0 - 1)
+    pub brightness: f32,
+    /// Scale frames down so they stay within a current budget
+    pub power_limit: Option<PowerLimit>,
+    /// Temporally dither the output to keep the precision lost when reducing to 8 bits
+    pub dither: bool,
+}
+
+impl Default for CorrectionOptions {
+    fn default() -> CorrectionOptions {
+        CorrectionOptions {
+            gamma: 2.2,
+            white_balance: [1.0; 3],
+            brightness: 1.0,
+            power_limit: None,
+            dither: false,
+        }
+    }
+}
+
+/// Maximum value of the 16 bit intermediate representation
+const FULL: f32 = u16::MAX as f32;
+/// Ratio between the 16 bit intermediate values and the 8 bit output
+const STEP: f32 = FULL / 255.0;
+
+/// Converts the colors of an `OutputMapper` into the values sent to LED chips.
+///
+/// Colors are gamma corrected into 16 bit, white balanced, scaled by the brightness and the
+/// power limit and then reduced back to 8 bits, optionally with temporal dithering.
+pub struct LedCorrection {
+    options: CorrectionOptions,
+    gamma_table: [u16; 256],
+    scratch: Vec<[f32; 3]>,
+    residual: Vec<[f32; 3]>,
+    last_milliamps: f32,
+}
+
+impl LedCorrection {
+    pub fn new(options: CorrectionOptions) -> LedCorrection {
+        LedCorrection {
+            gamma_table: gamma_table(options.gamma),
+            options,
+            scratch: Vec::new(),
+            residual: Vec::new(),
+            last_milliamps: 0.0,
+        }
+    }
+
+    pub fn options(&self) -> &CorrectionOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: CorrectionOptions) {
+        if options.gamma != self.options.gamma {
+            self.gamma_table = gamma_table(options.gamma);
+        }
+        self.options = options;
+    }
+
+    /// Estimated current of the last frame before the power limit was applied
+    pub fn last_milliamps(&self) -> f32 {
+        self.last_milliamps
+    }
+
+    /// Correct a frame in place
+    pub fn apply(&mut self, leds: &mut [[u8; 3]]) {
+        let opts = self.options;
+
+        self.scratch.clear();
+        self.scratch.extend(leds.iter().map(|led| {
+            let mut out = [0.0; 3];
+            for ((o, c), wb) in out.iter_mut().zip(led).zip(opts.white_balance) {
+                *o = (self.gamma_table[*c as usize] as f32 * wb * opts.brightness).clamp(0.0, FULL);
+            }
+            out
+        }));
+
+        let channel_sum: f32 = self.scratch.iter().flatten().sum::<f32>() / FULL;
+        if let Some(limit) = opts.power_limit {
+            let idle = limit.idle_milliamps_per_led * leds.len() as f32;
+            let active = channel_sum * limit.milliamps_per_channel;
+            self.last_milliamps = idle + active;
+
+            if self.last_milliamps > limit.max_milliamps && active > 0.0 {
+                let scale = ((limit.max_milliamps - idle) / active).clamp(0.0, 1.0);
+                self.scratch.iter_mut().flatten().for_each(|v| *v *= scale);
+            }
+        }
+
+        if opts.dither {
+            self.residual.resize(leds.len(), [0.0; 3]);
+        }
+        for (i, (led, value)) in leds.iter_mut().zip(&self.scratch).enumerate() {
+            for ch in 0..3 {
+                led[ch] = if opts.dither {
+                    let v = value[ch] + self.residual[i][ch];
+                    let out = (v / STEP).floor().clamp(0.0, 255.0);
+                    self.residual[i][ch] = v - out * STEP;
+                    out as u8
+                } else {
+                    (value[ch] / STEP).round() as u8
+                };
+            }
+        }
+    }
+
+    /// Correct a frame into a new buffer
+    pub fn process(&mut self, leds: &[[u8; 3]]) -> Vec<[u8; 3]> {
+        let mut out = leds.to_vec();
+        self.apply(&mut out);
+        out
+    }
+}
+
+fn gamma_table(gamma: f32) -> [u16; 256] {
+    let mut table = [0; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = ((i as f32 / 255.0).powf(gamma) * FULL).round() as u16;
+    }
+    table
+}
+
+/// Extract the white component of a color for RGBW LEDs
+pub fn rgb_to_rgbw([r, g, b]: [u8; 3]) -> [u8; 4] {
+    let w = r.min(g).min(b);
+    [r - w, g - w, b - w, w]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linear() -> CorrectionOptions {
+        CorrectionOptions {
+            gamma: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn linear_gamma_is_identity() {
+        let mut correction = LedCorrection::new(linear());
+        let leds: Vec<[u8; 3]> = (0..=255).map(|v| [v, v, v]).collect();
+        assert_eq!(correction.process(&leds), leds);
+    }
+
+    #[test]
+    fn gamma_darkens_midtones() {
+        let mut correction = LedCorrection::new(CorrectionOptions::default());
+        let [[r, _, _]] = correction.process(&[[128, 0, 255]])[..] else {
+            unreachable!()
+        };
+        // (128 / 255) ^ 2.2 * 255
+        assert_eq!(r, 56);
+        assert_eq!(correction.process(&[[0, 0, 255]]), [[0, 0, 255]]);
+    }
+
+    #[test]
+    fn white_balance_and_brightness() {
+        let mut correction = LedCorrection::new(CorrectionOptions {
+            white_balance: [1.0, 0.5, 0.25],
+            brightness: 0.5,
+            ..linear()
+        });
+        assert_eq!(correction.process(&[[200, 200, 200]]), [[100, 50, 25]]);
+    }
+
+    #[test]
+    fn power_limit_scales_frame() {
+        let limit = PowerLimit {
+            max_milliamps: 310.0,
+            milliamps_per_channel: 20.0,
+            idle_milliamps_per_led: 1.0,
+        };
+        let mut correction = LedCorrection::new(CorrectionOptions {
+            power_limit: Some(limit),
+            ..linear()
+        });
+
+        // 10 white LEDs draw 10 + 10 * 3 * 20 mA, 300 mA are left for the channels
+        let out = correction.process(&[[255; 3]; 10]);
+        assert_eq!(correction.last_milliamps(), 610.0);
+        assert_eq!(out, [[128; 3]; 10]);
+
+        // Frames within the budget are untouched
+        let out = correction.process(&[[255, 0, 0]; 10]);
+        assert_eq!(correction.last_milliamps(), 210.0);
+        assert_eq!(out, [[255, 0, 0]; 10]);
+    }
+
+    #[test]
+    fn dithering_keeps_average() {
+        let mut correction = LedCorrection::new(CorrectionOptions {
+            brightness: 0.5,
+            dither: true,
+            ..linear()
+        });
+
+        // 101 * 0.5 lies between two output values
+        let frames = 100;
+        let sum: u32 = (0..frames)
+            .map(|_| correction.process(&[[101, 0, 0]])[0][0] as u32)
+            .sum();
+        let average = sum as f32 / frames as f32;
+        assert!((average - 50.5).abs() < 0.02, "{average}");
+    }
+
+    #[test]
+    fn rgbw_extracts_white() {
+        assert_eq!(rgb_to_rgbw([200, 100, 50]), [150, 50, 0, 50]);
+        assert_eq!(rgb_to_rgbw([0, 0, 0]), [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,266 @@
+use super::{OutputMapper, floored_amplitudes, to_rgb8};
+use crate::Note;
+use crate::color::{ColorMap, ColorMapper};
+use crate::conf::OutputOptions;
+use serde::{Deserialize, Serialize};
+
+/// Options for `LinearOutput`, defaults match colorchord's `OutputLinear`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LinearOptions {
+    /// The strip is arranged in a circle, LEDs are rotated to minimize movement between frames
+    pub is_loop: bool,
+    /// Keep LEDs at full brightness instead of following the note amplitude
+    pub steady_bright: bool,
+    /// Amplification of the note amplitude before it's used as brightness
+    pub satamp: f32,
+    /// Exponent applied to note amplitudes, higher values give loud notes more LEDs
+    pub light_siding: f32,
+    /// Share of the total amplitude subtracted from every note, notes left with a smaller
+    /// share get no LEDs
+    pub led_floor: f32,
+    /// Maximum brightness
+    pub led_limit: f32,
+}
+
+impl Default for LinearOptions {
+    fn default() -> LinearOptions {
+        LinearOptions {
+            is_loop: false,
+            steady_bright: false,
+            satamp: 1.6,
+            light_siding: 1.9,
+            led_floor: 0.1,
+            led_limit: 1.0,
+        }
+    }
+}
+
+impl From<&OutputOptions> for LinearOptions {
+    fn from(conf: &OutputOptions) -> LinearOptions {
+        let defaults = LinearOptions::default();
+        LinearOptions {
+            is_loop: conf.is_loop.unwrap_or(defaults.is_loop),
+            steady_bright: conf.steady_bright.unwrap_or(defaults.steady_bright),
+            satamp: conf.satamp.unwrap_or(defaults.satamp),
+            light_siding: conf.light_siding.unwrap_or(defaults.light_siding),
+            led_floor: conf.led_floor.unwrap_or(defaults.led_floor),
+            led_limit: conf.led_limit.unwrap_or(defaults.led_limit),
+        }
+    }
+}
+
+/// Drives a LED strip by giving every note a share of the LEDs proportional to its amplitude.
+pub struct LinearOutput {
+    options: LinearOptions,
+    color_map: ColorMap,
+    leds: usize,
+    last_advance: usize,
+    last_position: Vec<f32>,
+    last_position_filter: Vec<f32>,
+}
+
+/// Shortest distance between two positions on the note wheel
+fn wheel_distance(a: f32, b: f32) -> f32 {
+    let diff = (a - b).abs();
+    diff.min(1.0 - diff)
+}
+
+impl LinearOutput {
+    pub fn new(leds: usize, options: LinearOptions) -> LinearOutput {
+        LinearOutput {
+            options,
+            color_map: ColorMap::default(),
+            leds,
+            last_advance: 0,
+            last_position: vec![0.0; leds],
+            last_position_filter: vec![0.0; leds],
+        }
+    }
+
+    /// Create an output using the `leds` count and linear options from a colorchord configuration
+    pub fn from_conf(conf: &OutputOptions) -> LinearOutput {
+        LinearOutput::new(conf.leds.unwrap_or(300), conf.into())
+    }
+
+    pub fn options(&self) -> &LinearOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut LinearOptions {
+        &mut self.options
+    }
+
+    /// Color mapping used for notes, colorchord's hue wheel by default
+    pub fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    pub fn set_color_map(&mut self, color_map: ColorMap) {
+        self.color_map = color_map;
+    }
+}
+
+impl OutputMapper for LinearOutput {
+    fn leds(&self) -> usize {
+        self.leds
+    }
+
+    fn update(&mut self, notes: &[Note]) -> Vec<[u8; 3]> {
+        let leds = self.leds;
+        if leds == 0 {
+            return Vec::new();
+        }
+        let opts = self.options;
+
+        // Step 1: Amplitude of every note, slow and fast
+        let amps: Vec<(f32, f32, f32)> = notes
+            .iter()
+            .zip(floored_amplitudes(notes, opts.light_siding, opts.led_floor))
+            .map(|(n, (slow, fast))| (n.id.rem_euclid(1.0), slow, fast))
+            .collect();
+        let mut total: f32 = amps.iter().map(|a| a.1).sum();
+        if total <= 0.0 {
+            total = 0.01;
+        }
+
+        // Step 2: Allocate LEDs to notes
+        let mut allocated: Vec<(f32, f32, f32)> = Vec::with_capacity(leds);
+        for &(position, slow, fast) in &amps {
+            let count = ((slow / total) * leds as f32) as usize;
+            allocated.extend(std::iter::repeat_n((position, slow, fast), count));
+        }
+        allocated.truncate(leds);
+        let last = allocated.last().copied().unwrap_or((0.0, 0.0, 0.0));
+        allocated.resize(leds, last);
+
+        // Step 3: On loops rotate the LEDs to minimize the movement from the last frame
+        let mut advance = 0;
+        if opts.is_loop {
+            let mut min_diff = f32::MAX;
+            for i in 0..leds {
+                let mut diff: f32 = (0..leds)
+                    .map(|j| {
+                        wheel_distance(self.last_position_filter[j], allocated[(j + i) % leds].0)
+                    })
+                    .sum();
+
+                let mut advance_diff = self.last_advance.abs_diff(i);
+                if advance_diff > leds / 2 {
+                    advance_diff = leds - advance_diff;
+                }
+                let ad = advance_diff as f32 / leds as f32;
+                diff += ad * ad;
+
+                if diff < min_diff {
+                    min_diff = diff;
+                    advance = i;
+                }
+            }
+        }
+        self.last_advance = advance;
+
+        let output = (0..leds)
+            .map(|i| {
+                let (position, slow, fast) = allocated[(i + advance) % leds];
+                let sat = (slow * opts.satamp).max(fast * opts.satamp).min(1.0);
+                self.last_position[i] = position;
+
+                let value = if opts.steady_bright { 1.0 } else { sat };
+                to_rgb8(self.color_map.map(position, 1.0, value.min(opts.led_limit)))
+            })
+            .collect();
+
+        if opts.is_loop {
+            for (filter, position) in self
+                .last_position_filter
+                .iter_mut()
+                .zip(&self.last_position)
+            {
+                *filter = *filter * 0.9 + position * 0.1;
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::note;
+    use super::*;
+
+    /// Amplitudes map straight to LED shares and brightness
+    fn plain() -> LinearOptions {
+        LinearOptions {
+            satamp: 1.0,
+            light_siding: 1.0,
+            led_floor: 0.0,
+            ..Default::default()
+        }
+    }
+
+    fn color(position: f32, value: f32) -> [u8; 3] {
+        to_rgb8(ColorMap::default().map(position, 1.0, value))
+    }
+
+    #[test]
+    fn allocates_leds_proportionally() {
+        let mut strip = LinearOutput::new(8, plain());
+        let leds = strip.update(&[note(0.0, 0.75), note(0.5, 0.25)]);
+        assert_eq!(leds[..6], [color(0.0, 0.75); 6]);
+        assert_eq!(leds[6..], [color(0.5, 0.25); 2]);
+    }
+
+    #[test]
+    fn quiet_mixes_stay_lit() {
+        let mut strip = LinearOutput::new(4, LinearOptions::default());
+        let leds = strip.update(&[note(0.0, 0.1), note(0.5, 0.1)]);
+        assert!(leds.iter().all(|led| *led != [0; 3]));
+    }
+
+    #[test]
+    fn loop_rotates_to_keep_positions() {
+        let mut strip = LinearOutput::new(
+            8,
+            LinearOptions {
+                is_loop: true,
+                ..plain()
+            },
+        );
+        let mut leds = Vec::new();
+        for _ in 0..50 {
+            leds = strip.update(&[note(0.1, 0.5), note(0.6, 0.5)]);
+        }
+        let first = leds.iter().filter(|led| **led == color(0.1, 0.5)).count();
+        assert_eq!(first, 4);
+
+        // The same notes in the opposite order are rotated back into place
+        let swapped = strip.update(&[note(0.6, 0.5), note(0.1, 0.5)]);
+        assert_eq!(swapped, leds);
+
+        // Without is_loop the order follows the notes
+        let mut strip = LinearOutput::new(8, plain());
+        strip.update(&[note(0.1, 0.5), note(0.6, 0.5)]);
+        assert_eq!(
+            strip.update(&[note(0.6, 0.5), note(0.1, 0.5)])[..4],
+            [color(0.6, 0.5); 4]
+        );
+    }
+
+    #[test]
+    fn steady_bright_ignores_amplitude() {
+        let notes = [note(0.3, 0.2)];
+        let mut dim = LinearOutput::new(2, plain());
+        assert_eq!(dim.update(&notes), [color(0.3, 0.2); 2]);
+
+        let mut steady = LinearOutput::new(
+            2,
+            LinearOptions {
+                steady_bright: true,
+                led_limit: 0.5,
+                ..plain()
+            },
+        );
+        assert_eq!(steady.update(&notes), [color(0.3, 0.5); 2]);
+    }
+}
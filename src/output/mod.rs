@@ -0,0 +1,103 @@
+//! Mapping notes onto LED colors, ported from colorchord's output drivers.
+
+mod cells;
+mod correction;
+mod linear;
+mod prominent;
+mod smoothing;
+mod voronoi;
+
+use crate::Note;
+
+pub use cells::{CellsOptions, CellsOutput};
+pub use correction::{CorrectionOptions, LedCorrection, PowerLimit, rgb_to_rgbw};
+pub use linear::{LinearOptions, LinearOutput};
+pub use prominent::{ProminentOptions, ProminentOutput};
+pub use smoothing::{ColorSpace, Envelope, LedSmoother, NoteSmoother, SmoothingOptions};
+pub use voronoi::{VoronoiOptions, VoronoiOutput};
+
+/// Turns the notes of a frame into colors for a fixture.
+///
+/// Mappers keep state between frames, call `update` once per notefinder frame.
+pub trait OutputMapper {
+    /// Number of LEDs produced by `update`
+    fn leds(&self) -> usize;
+
+    /// Compute the LED colors for the current notes
+    fn update(&mut self, notes: &[Note]) -> Vec<[u8; 3]>;
+}
+
+/// Slow and fast amplitude of every note raised to `light_siding`, with colorchord's LED floor.
+///
+/// `led_floor` of the total slow amplitude is subtracted from every note and notes left with
+/// less than `led_floor` of the total are dropped, so the floor scales with the mix.
+fn floored_amplitudes(notes: &[Note], light_siding: f32, led_floor: f32) -> Vec<(f32, f32)> {
+    let raw: Vec<(f32, f32)> = notes
+        .iter()
+        .map(|n| {
+            (
+                n.amplitude_iir2.max(0.0).powf(light_siding),
+                n.amplitude_out.max(0.0).powf(light_siding),
+            )
+        })
+        .collect();
+    let total: f32 = raw.iter().map(|a| a.0).sum();
+
+    raw.into_iter()
+        .map(|(slow, fast)| {
+            let slow = slow - led_floor * total;
+            if total > 0.0 && slow / total >= led_floor {
+                (slow, fast)
+            } else {
+                (0.0, 0.0)
+            }
+        })
+        .collect()
+}
+
+/// Convert a color from `cc_to_rgb` to 8 bit channels
+pub fn to_rgb8(color: [f32; 3]) -> [u8; 3] {
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NoteDists;
+
+    /// Active note with the same slow and fast amplitude
+    pub(super) fn note(id: f32, amplitude: f32) -> Note {
+        Note {
+            active: true,
+            id,
+            dist: NoteDists {
+                amp: 0.0,
+                mean: 0.0,
+                sigma: 0.0,
+                taken: false,
+            },
+            amplitude_out: amplitude,
+            amplitude_iir2: amplitude,
+            endured: 0,
+        }
+    }
+
+    #[test]
+    fn led_floor_is_relative_to_the_mix() {
+        // Quiet mixes keep their notes
+        let quiet = floored_amplitudes(&[note(0.0, 0.02), note(0.5, 0.01)], 1.0, 0.1);
+        assert!((quiet[0].0 - 0.017).abs() < 1e-6);
+        assert!((quiet[1].0 - 0.007).abs() < 1e-6);
+        assert_eq!(quiet[1].1, 0.01);
+
+        // Notes below the floor share of a loud mix are dropped
+        let loud = floored_amplitudes(&[note(0.0, 1.0), note(0.5, 0.1)], 1.0, 0.1);
+        assert!((loud[0].0 - 0.89).abs() < 1e-6);
+        assert_eq!(loud[1], (0.0, 0.0));
+
+        assert_eq!(
+            floored_amplitudes(&[note(0.0, 0.0)], 1.9, 0.1),
+            [(0.0, 0.0)]
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use super::{OutputMapper, to_rgb8};
+use crate::Note;
+use crate::color::{ColorMap, ColorMapper};
+use crate::conf::OutputOptions;
+use serde::{Deserialize, Serialize};
+
+/// Options for `ProminentOutput`, defaults match colorchord's `OutputProminent`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProminentOptions {
+    /// Keep LEDs at full brightness instead of following the note amplitude
+    pub steady_bright: bool,
+    /// Amplification of the note amplitude before it's used as brightness
+    pub satamp: f32,
+}
+
+impl Default for ProminentOptions {
+    fn default() -> ProminentOptions {
+        ProminentOptions {
+            steady_bright: false,
+            satamp: 1.6,
+        }
+    }
+}
+
+impl From<&OutputOptions> for ProminentOptions {
+    fn from(conf: &OutputOptions) -> ProminentOptions {
+        let defaults = ProminentOptions::default();
+        ProminentOptions {
+            steady_bright: conf.steady_bright.unwrap_or(defaults.steady_bright),
+            satamp: conf.satamp.unwrap_or(defaults.satamp),
+        }
+    }
+}
+
+/// Colors the whole fixture after the loudest note.
+pub struct ProminentOutput {
+    options: ProminentOptions,
+    color_map: ColorMap,
+    leds: usize,
+}
+
+impl ProminentOutput {
+    pub fn new(leds: usize, options: ProminentOptions) -> ProminentOutput {
+        ProminentOutput {
+            options,
+            color_map: ColorMap::default(),
+            leds,
+        }
+    }
+
+    /// Create an output using the `leds` count and prominent options from a colorchord configuration
+    pub fn from_conf(conf: &OutputOptions) -> ProminentOutput {
+        ProminentOutput::new(conf.leds.unwrap_or(300), conf.into())
+    }
+
+    pub fn options(&self) -> &ProminentOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut ProminentOptions {
+        &mut self.options
+    }
+
+    /// Color mapping used for notes, colorchord's hue wheel by default
+    pub fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    pub fn set_color_map(&mut self, color_map: ColorMap) {
+        self.color_map = color_map;
+    }
+}
+
+impl OutputMapper for ProminentOutput {
+    fn leds(&self) -> usize {
+        self.leds
+    }
+
+    fn update(&mut self, notes: &[Note]) -> Vec<[u8; 3]> {
+        let opts = self.options;
+        let (position, amp) = notes
+            .iter()
+            .map(|n| (n.id, n.amplitude_iir2 * opts.satamp))
+            .fold((0.0, 0.0), |best, n| if n.1 > best.1 { n } else { best });
+
+        let value = if opts.steady_bright && amp > 0.0 {
+            1.0
+        } else {
+            amp.min(1.0)
+        };
+        vec![to_rgb8(self.color_map.map(position, 1.0, value)); self.leds]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::note;
+    use super::*;
+
+    fn color(position: f32, value: f32) -> [u8; 3] {
+        to_rgb8(ColorMap::default().map(position, 1.0, value))
+    }
+
+    #[test]
+    fn follows_the_loudest_note() {
+        let options = ProminentOptions {
+            satamp: 1.0,
+            ..Default::default()
+        };
+        let mut output = ProminentOutput::new(3, options);
+        assert_eq!(
+            output.update(&[note(0.1, 0.2), note(0.6, 0.5)]),
+            [color(0.6, 0.5); 3]
+        );
+        assert_eq!(
+            output.update(&[note(0.1, 0.7), note(0.6, 0.5)]),
+            [color(0.1, 0.7); 3]
+        );
+        assert_eq!(output.update(&[]), [[0; 3]; 3]);
+    }
+
+    #[test]
+    fn steady_bright_ignores_amplitude() {
+        let options = ProminentOptions {
+            steady_bright: true,
+            ..Default::default()
+        };
+        let mut output = ProminentOutput::new(1, options);
+        assert_eq!(output.update(&[note(0.4, 0.1)]), [color(0.4, 1.0)]);
+    }
+}
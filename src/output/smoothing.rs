@@ -0,0 +1,410 @@
+use crate::Note;
+use crate::color::{oklab_to_srgb, srgb_to_oklab};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Space LED colors are interpolated in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// Interpolate the 8 bit channels directly
+    Rgb,
+    /// Interpolate in OKLab, fades keep their hue and change brightness evenly
+    #[default]
+    Oklab,
+}
+
+/// Options for `Envelope`, `NoteSmoother` and `LedSmoother`.
+///
+/// Rates are the share of the distance to the target covered every frame, 1.0 follows
+/// the input immediately.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SmoothingOptions {
+    /// Rate used while the value is rising
+    pub attack: f32,
+    /// Rate used while the value is falling
+    pub release: f32,
+    /// Number of frames a peak is held before the release starts
+    pub peak_hold: u32,
+    /// Share of the previous value kept every frame while falling, 0 disables the decay.
+    ///
+    /// Gives an afterglow that fades out exponentially regardless of the release rate.
+    pub decay: f32,
+    /// Space LED colors are interpolated in
+    pub color_space: ColorSpace,
+}
+
+impl Default for SmoothingOptions {
+    fn default() -> SmoothingOptions {
+        SmoothingOptions {
+            attack: 1.0,
+            release: 0.3,
+            peak_hold: 0,
+            decay: 0.0,
+            color_space: ColorSpace::default(),
+        }
+    }
+}
+
+impl ColorSpace {
+    fn encode(self, led: [u8; 3]) -> [f32; 3] {
+        let color = led.map(|c| c as f32 / 255.0);
+        match self {
+            ColorSpace::Rgb => color,
+            ColorSpace::Oklab => srgb_to_oklab(color),
+        }
+    }
+
+    fn decode(self, color: [f32; 3]) -> [u8; 3] {
+        let color = match self {
+            ColorSpace::Rgb => color,
+            ColorSpace::Oklab => oklab_to_srgb(color),
+        };
+        color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    fn brightness(self, color: [f32; 3]) -> f32 {
+        match self {
+            ColorSpace::Rgb => color[0].max(color[1]).max(color[2]),
+            ColorSpace::Oklab => color[0],
+        }
+    }
+}
+
+impl SmoothingOptions {
+    /// Interpolation factor towards the target for the current frame, updates the hold counter
+    fn rate(&self, current: f32, target: f32, hold: &mut u32) -> f32 {
+        if target >= current {
+            *hold = self.peak_hold;
+            self.attack.clamp(0.0, 1.0)
+        } else if *hold > 0 {
+            *hold -= 1;
+            0.0
+        } else {
+            self.release.clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Attack / release envelope follower for a single value.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Envelope {
+    value: f32,
+    hold: u32,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope::default()
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Move towards `target` and return the new value
+    pub fn update(&mut self, target: f32, options: &SmoothingOptions) -> f32 {
+        let rate = options.rate(self.value, target, &mut self.hold);
+        let next = self.value + (target - self.value) * rate;
+        self.value = next.max(self.value * options.decay);
+        self.value
+    }
+
+    pub fn reset(&mut self) {
+        *self = Envelope::default();
+    }
+}
+
+struct SmoothedNote {
+    note: Note,
+    amplitude_out: Envelope,
+    amplitude_iir2: Envelope,
+}
+
+/// Smooths note amplitudes between frames, notes are followed by their enduring id.
+///
+/// Notes that disappear are released instead of cut off, they are kept until their
+/// amplitude falls below `cutoff`.
+pub struct NoteSmoother {
+    options: SmoothingOptions,
+    cutoff: f32,
+    notes: HashMap<i32, SmoothedNote>,
+}
+
+impl NoteSmoother {
+    pub fn new(options: SmoothingOptions) -> NoteSmoother {
+        NoteSmoother {
+            options,
+            cutoff: 0.001,
+            notes: HashMap::new(),
+        }
+    }
+
+    pub fn options(&self) -> &SmoothingOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut SmoothingOptions {
+        &mut self.options
+    }
+
+    /// Amplitude below which released notes are dropped
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.cutoff = cutoff;
+    }
+
+    /// Smooth the next frame of notes, for example from `get_notes()`
+    pub fn update(&mut self, notes: &[Note]) -> Vec<Note> {
+        let opts = self.options;
+
+        for smoothed in self.notes.values_mut() {
+            smoothed.note.active = false;
+        }
+        for note in notes.iter().filter(|n| n.active) {
+            let smoothed = self
+                .notes
+                .entry(note.endured)
+                .or_insert_with(|| SmoothedNote {
+                    note: *note,
+                    amplitude_out: Envelope::new(),
+                    amplitude_iir2: Envelope::new(),
+                });
+            smoothed.note = *note;
+        }
+
+        let mut output = Vec::with_capacity(self.notes.len());
+        self.notes.retain(|_, smoothed| {
+            let (target_out, target_iir2) = if smoothed.note.active {
+                (smoothed.note.amplitude_out, smoothed.note.amplitude_iir2)
+            } else {
+                (0.0, 0.0)
+            };
+            let out = smoothed.amplitude_out.update(target_out, &opts);
+            let iir2 = smoothed.amplitude_iir2.update(target_iir2, &opts);
+
+            let keep = smoothed.note.active || out.max(iir2) > self.cutoff;
+            if keep {
+                output.push(Note {
+                    active: true,
+                    amplitude_out: out,
+                    amplitude_iir2: iir2,
+                    ..smoothed.note
+                });
+            }
+            keep
+        });
+
+        // Keep a stable order between frames
+        output.sort_by(|a, b| a.id.total_cmp(&b.id));
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.notes.clear();
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct LedState {
+    color: [f32; 3],
+    hold: u32,
+}
+
+/// Smooths the colors of a LED buffer between frames.
+///
+/// Every LED follows its target with the attack / release envelope, brightness decides
+/// whether a LED is rising or falling.
+pub struct LedSmoother {
+    options: SmoothingOptions,
+    leds: Vec<LedState>,
+}
+
+impl LedSmoother {
+    pub fn new(options: SmoothingOptions) -> LedSmoother {
+        LedSmoother {
+            options,
+            leds: Vec::new(),
+        }
+    }
+
+    pub fn options(&self) -> &SmoothingOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: SmoothingOptions) {
+        if options.color_space != self.options.color_space {
+            self.leds.clear();
+        }
+        self.options = options;
+    }
+
+    /// Smooth a frame in place
+    pub fn apply(&mut self, leds: &mut [[u8; 3]]) {
+        let opts = self.options;
+        self.leds.resize(leds.len(), LedState::default());
+
+        let space = opts.color_space;
+        for (led, state) in leds.iter_mut().zip(&mut self.leds) {
+            let target = space.encode(*led);
+            let current = space.brightness(state.color);
+            let rate = opts.rate(current, space.brightness(target), &mut state.hold);
+
+            let mut color: [f32; 3] =
+                std::array::from_fn(|ch| state.color[ch] + (target[ch] - state.color[ch]) * rate);
+            // OKLab scales towards black with the lightness, so the hue is kept while fading
+            if space.brightness(color) < current * opts.decay {
+                color = state.color.map(|c| c * opts.decay);
+            }
+
+            state.color = color;
+            *led = space.decode(color);
+        }
+    }
+
+    /// Smooth a frame into a new buffer
+    pub fn process(&mut self, leds: &[[u8; 3]]) -> Vec<[u8; 3]> {
+        let mut out = leds.to_vec();
+        self.apply(&mut out);
+        out
+    }
+
+    pub fn reset(&mut self) {
+        self.leds.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::note;
+    use super::*;
+
+    fn options(attack: f32, release: f32) -> SmoothingOptions {
+        SmoothingOptions {
+            attack,
+            release,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn envelope_attack_and_release() {
+        let opts = options(0.5, 0.25);
+        let mut envelope = Envelope::new();
+        assert_close(envelope.update(1.0, &opts), 0.5);
+        assert_close(envelope.update(1.0, &opts), 0.75);
+        assert_close(envelope.update(0.0, &opts), 0.5625);
+        assert_close(envelope.update(0.0, &opts), 0.421875);
+
+        envelope.reset();
+        assert_eq!(envelope.value(), 0.0);
+    }
+
+    #[test]
+    fn envelope_holds_peaks() {
+        let opts = SmoothingOptions {
+            peak_hold: 2,
+            ..options(1.0, 1.0)
+        };
+        let mut envelope = Envelope::new();
+        envelope.update(1.0, &opts);
+        assert_eq!(envelope.update(0.0, &opts), 1.0);
+        assert_eq!(envelope.update(0.0, &opts), 1.0);
+        assert_eq!(envelope.update(0.0, &opts), 0.0);
+    }
+
+    #[test]
+    fn envelope_decays_exponentially() {
+        let opts = SmoothingOptions {
+            decay: 0.5,
+            ..options(1.0, 1.0)
+        };
+        let mut envelope = Envelope::new();
+        envelope.update(1.0, &opts);
+        assert_eq!(envelope.update(0.0, &opts), 0.5);
+        assert_eq!(envelope.update(0.0, &opts), 0.25);
+        // Rising values aren't held back by the decay
+        assert_eq!(envelope.update(0.8, &opts), 0.8);
+    }
+
+    fn endured(id: f32, amplitude: f32, endured: i32) -> Note {
+        Note {
+            endured,
+            ..note(id, amplitude)
+        }
+    }
+
+    #[test]
+    fn notes_are_followed_by_enduring_id() {
+        let mut smoother = NoteSmoother::new(options(0.5, 0.5));
+        smoother.update(&[endured(0.2, 1.0, 7)]);
+
+        // The position moved but it's the same note, so its envelope continues
+        let notes = smoother.update(&[endured(0.25, 1.0, 7)]);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, 0.25);
+        assert_close(notes[0].amplitude_out, 0.75);
+
+        // A new enduring id starts from 0
+        let notes = smoother.update(&[endured(0.25, 1.0, 7), endured(0.6, 1.0, 8)]);
+        assert_eq!(notes.len(), 2);
+        assert_close(notes[1].amplitude_out, 0.5);
+    }
+
+    #[test]
+    fn vanished_notes_are_released() {
+        let mut smoother = NoteSmoother::new(options(1.0, 0.5));
+        smoother.set_cutoff(0.2);
+        smoother.update(&[endured(0.4, 1.0, 3)]);
+
+        let notes = smoother.update(&[]);
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].active);
+        assert_close(notes[0].amplitude_out, 0.5);
+        assert_close(smoother.update(&[])[0].amplitude_out, 0.25);
+        assert!(smoother.update(&[]).is_empty());
+
+        // Inactive notes count as gone
+        let inactive = Note {
+            active: false,
+            ..endured(0.4, 1.0, 4)
+        };
+        assert!(smoother.update(&[inactive]).is_empty());
+    }
+
+    #[test]
+    fn leds_fade_through_oklab() {
+        let red = [255, 0, 0];
+        let blue = [0, 0, 255];
+        let opts = options(1.0, 0.5);
+
+        let mut smoother = LedSmoother::new(opts);
+        assert_eq!(smoother.process(&[red]), [red]);
+        let mid = smoother.process(&[blue])[0];
+
+        let (a, b) = (
+            srgb_to_oklab([1.0, 0.0, 0.0]),
+            srgb_to_oklab([0.0, 0.0, 1.0]),
+        );
+        let expected = ColorSpace::Oklab.decode(std::array::from_fn(|i| (a[i] + b[i]) / 2.0));
+        assert_eq!(mid, expected);
+
+        let mut rgb = LedSmoother::new(SmoothingOptions {
+            color_space: ColorSpace::Rgb,
+            ..opts
+        });
+        rgb.process(&[red]);
+        assert_eq!(rgb.process(&[[0, 0, 128]]), [[128, 0, 64]]);
+    }
+
+    #[test]
+    fn leds_keep_their_hue_while_fading_out() {
+        let mut smoother = LedSmoother::new(options(1.0, 0.5));
+        smoother.process(&[[255, 128, 0]]);
+        let [r, g, b] = smoother.process(&[[0; 3]])[0];
+        assert!(r > g && g > b && r < 255);
+        assert!((g as f32 / r as f32 - 128.0 / 255.0).abs() < 0.1);
+    }
+}
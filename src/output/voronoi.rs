@@ -0,0 +1,169 @@
+use super::{OutputMapper, to_rgb8};
+use crate::Note;
+use crate::color::{ColorMap, ColorMapper};
+use crate::conf::OutputOptions;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+/// Options for `VoronoiOutput`, defaults match colorchord's `default.conf`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VoronoiOptions {
+    /// Notes with an amplitude below this don't get a region
+    pub cutoff: f32,
+    /// Amplification of the note amplitude before it's used as brightness
+    pub satamp: f32,
+    /// Exponent applied to note amplitudes when weighting regions
+    pub amppow: f32,
+    /// Exponent applied to distances, higher values give sharper region borders
+    pub distpow: f32,
+    /// Seed the regions at the edges of the grid instead of around the center
+    pub from_sides: bool,
+    /// How much of the previous frame is kept, 0 disables persistence
+    pub decay: f32,
+}
+
+impl Default for VoronoiOptions {
+    fn default() -> VoronoiOptions {
+        VoronoiOptions {
+            cutoff: 0.03,
+            satamp: 2.0,
+            amppow: 2.51,
+            distpow: 1.5,
+            from_sides: true,
+            decay: 0.0,
+        }
+    }
+}
+
+impl From<&OutputOptions> for VoronoiOptions {
+    fn from(conf: &OutputOptions) -> VoronoiOptions {
+        let defaults = VoronoiOptions::default();
+        VoronoiOptions {
+            cutoff: conf.shape_cutoff.unwrap_or(defaults.cutoff),
+            satamp: conf.satamp.unwrap_or(defaults.satamp),
+            amppow: conf.amppow.unwrap_or(defaults.amppow),
+            distpow: conf.distpow.unwrap_or(defaults.distpow),
+            from_sides: conf.fromsides.unwrap_or(defaults.from_sides),
+            decay: conf.decay.unwrap_or(defaults.decay),
+        }
+    }
+}
+
+struct Seed {
+    x: f32,
+    y: f32,
+    weight: f32,
+    color: [f32; 3],
+}
+
+/// Colors a width × height grid by giving every note a region weighted by its amplitude.
+///
+/// Notes are placed around a circle by pitch, every pixel takes the color of the note with
+/// the highest amplitude to distance ratio.
+pub struct VoronoiOutput {
+    options: VoronoiOptions,
+    color_map: ColorMap,
+    width: usize,
+    height: usize,
+    frame: Vec<[f32; 3]>,
+}
+
+impl VoronoiOutput {
+    pub fn new(width: usize, height: usize, options: VoronoiOptions) -> VoronoiOutput {
+        VoronoiOutput {
+            options,
+            color_map: ColorMap::default(),
+            width,
+            height,
+            frame: vec![[0.0; 3]; width * height],
+        }
+    }
+
+    /// Create an output using the `lightx`, `lighty` and voronoi options from a colorchord configuration
+    pub fn from_conf(conf: &OutputOptions) -> VoronoiOutput {
+        VoronoiOutput::new(
+            conf.lightx.unwrap_or(16),
+            conf.lighty.unwrap_or(16),
+            conf.into(),
+        )
+    }
+
+    pub fn options(&self) -> &VoronoiOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut VoronoiOptions {
+        &mut self.options
+    }
+
+    /// Color mapping used for notes, colorchord's hue wheel by default
+    pub fn color_map(&self) -> &ColorMap {
+        &self.color_map
+    }
+
+    pub fn set_color_map(&mut self, color_map: ColorMap) {
+        self.color_map = color_map;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+/// The framebuffer is laid out rows first
+impl OutputMapper for VoronoiOutput {
+    fn leds(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn update(&mut self, notes: &[Note]) -> Vec<[u8; 3]> {
+        let opts = self.options;
+        let radius = if opts.from_sides { 0.5 } else { 0.25 };
+
+        let seeds: Vec<Seed> = notes
+            .iter()
+            .filter(|n| n.active && n.amplitude_out > opts.cutoff)
+            .map(|n| {
+                let angle = n.id.rem_euclid(1.0) * TAU;
+                let value = (n.amplitude_out * opts.satamp).min(1.0);
+                Seed {
+                    x: 0.5 + angle.cos() * radius,
+                    y: 0.5 + angle.sin() * radius,
+                    weight: n.amplitude_iir2.max(0.0).powf(opts.amppow),
+                    color: self.color_map.map(n.id, 1.0, value),
+                }
+            })
+            .collect();
+
+        for y in 0..self.height {
+            let ly = (y as f32 + 0.5) / self.height as f32;
+            for x in 0..self.width {
+                let lx = (x as f32 + 0.5) / self.width as f32;
+
+                let mut best = None;
+                let mut best_match = 0.0;
+                for seed in &seeds {
+                    let (dx, dy) = (lx - seed.x, ly - seed.y);
+                    let dist = (dx * dx + dy * dy).max(1e-6).powf(opts.distpow);
+                    let score = seed.weight / dist;
+                    if score > best_match {
+                        best_match = score;
+                        best = Some(seed.color);
+                    }
+                }
+
+                let color = best.unwrap_or([0.0; 3]);
+                let pixel = &mut self.frame[y * self.width + x];
+                for (p, c) in pixel.iter_mut().zip(color) {
+                    *p = c.max(*p * opts.decay);
+                }
+            }
+        }
+
+        self.frame.iter().map(|&c| to_rgb8(c)).collect()
+    }
+}
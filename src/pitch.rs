@@ -0,0 +1,250 @@
+use crate::{Note, Notefinder};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Reference tuning used for MIDI note numbers
+pub const A4_HZ: f32 = 440.0;
+
+const A4_MIDI: i32 = 69;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PitchClass {
+    C,
+    CSharp,
+    D,
+    DSharp,
+    E,
+    F,
+    FSharp,
+    G,
+    GSharp,
+    A,
+    ASharp,
+    B,
+}
+
+impl PitchClass {
+    /// Pitch class of a MIDI note number
+    pub fn from_midi(midi: i32) -> PitchClass {
+        use PitchClass::*;
+        match midi.rem_euclid(12) {
+            0 => C,
+            1 => CSharp,
+            2 => D,
+            3 => DSharp,
+            4 => E,
+            5 => F,
+            6 => FSharp,
+            7 => G,
+            8 => GSharp,
+            9 => A,
+            10 => ASharp,
+            _ => B,
+        }
+    }
+
+    /// Semitones above C
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Name using sharps, for example `"C#"`
+    pub fn name(self) -> &'static str {
+        use PitchClass::*;
+        match self {
+            C => "C",
+            CSharp => "C#",
+            D => "D",
+            DSharp => "D#",
+            E => "E",
+            F => "F",
+            FSharp => "F#",
+            G => "G",
+            GSharp => "G#",
+            A => "A",
+            ASharp => "A#",
+            B => "B",
+        }
+    }
+}
+
+impl fmt::Display for PitchClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Absolute pitch of a frequency relative to `A4_HZ`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pitch {
+    /// Frequency in Hz
+    pub frequency: f32,
+    /// Nearest MIDI note number
+    pub midi: i32,
+    /// Offset from the nearest MIDI note in cents (-50 - 50)
+    pub cents: f32,
+    pub pitch_class: PitchClass,
+    /// Scientific pitch notation octave, middle C is in octave 4
+    pub octave: i32,
+}
+
+impl Pitch {
+    pub fn from_frequency(frequency: f32) -> Pitch {
+        let midi_exact = A4_MIDI as f32 + 12.0 * (frequency / A4_HZ).log2();
+        let midi = midi_exact.round() as i32;
+        Pitch {
+            frequency,
+            midi,
+            cents: (midi_exact - midi as f32) * 100.0,
+            pitch_class: PitchClass::from_midi(midi),
+            octave: midi.div_euclid(12) - 1,
+        }
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.pitch_class, self.octave)
+    }
+}
+
+/// Frequency in Hz of a MIDI note number
+pub fn midi_to_frequency(midi: f32) -> f32 {
+    A4_HZ * 2f32.powf((midi - A4_MIDI as f32) / 12.0)
+}
+
+impl Note {
+    /// Frequency of the note in the octave starting at `base_hz`.
+    ///
+    /// Notes are folded into a single octave, so this is the lowest frequency the note could have.
+    pub fn frequency(&self, base_hz: f32) -> f32 {
+        base_hz * 2f32.powf(self.id.rem_euclid(1.0))
+    }
+
+    /// Pitch class of the note when the notefinder starts at `base_hz`
+    pub fn pitch_class(&self, base_hz: f32) -> PitchClass {
+        Pitch::from_frequency(self.frequency(base_hz)).pitch_class
+    }
+}
+
+/// Octaves between `base_hz` and a note at `position` within the octave.
+///
+/// The octave is the one whose DFT bin at the position has the most energy. A position
+/// that rounds up to the next octave is looked up in bin 0 of the octave above.
+fn octaves_above_base(outbins: &[f32], frequency_bins: usize, position: f32) -> f32 {
+    let rounded = (position * frequency_bins as f32).round() as usize;
+    let (bin, wrapped) = if rounded >= frequency_bins {
+        (0, true)
+    } else {
+        (rounded, false)
+    };
+
+    let octave = outbins
+        .chunks_exact(frequency_bins)
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a[bin].total_cmp(&b[bin]))
+        .map(|(octave, _)| octave)
+        .unwrap_or(0);
+
+    if wrapped && octave > 0 {
+        // Just below bin 0 of `octave`
+        octave as f32 - 1.0 + position
+    } else {
+        octave as f32 + position
+    }
+}
+
+impl Notefinder {
+    /// Frequency of a note in Hz.
+    ///
+    /// The octave is picked from the DFT bin with the most energy at the note position.
+    pub fn note_frequency(&self, note: &Note) -> f32 {
+        let octaves = octaves_above_base(
+            self.get_outbins(),
+            self.frequency_bins() as usize,
+            note.id.rem_euclid(1.0),
+        );
+        self.base_hz() * 2f32.powf(octaves)
+    }
+
+    /// Absolute pitch of a note, see `note_frequency`
+    pub fn note_pitch(&self, note: &Note) -> Pitch {
+        Pitch::from_frequency(self.note_frequency(note))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a4_round_trips() {
+        let pitch = Pitch::from_frequency(440.0);
+        assert_eq!(pitch.midi, 69);
+        assert_eq!(pitch.pitch_class, PitchClass::A);
+        assert_eq!(pitch.octave, 4);
+        assert!(pitch.cents.abs() < 1e-3);
+        assert_eq!(pitch.to_string(), "A4");
+        assert!((midi_to_frequency(69.0) - 440.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn octave_boundaries() {
+        assert_eq!(
+            Pitch::from_frequency(midi_to_frequency(60.0)).to_string(),
+            "C4"
+        );
+        assert_eq!(
+            Pitch::from_frequency(midi_to_frequency(59.0)).to_string(),
+            "B3"
+        );
+        // 40 cents flat of C4 is still C4
+        let flat_c = Pitch::from_frequency(midi_to_frequency(59.6));
+        assert_eq!(flat_c.to_string(), "C4");
+        assert!((flat_c.cents + 40.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn pitch_class_from_negative_midi() {
+        assert_eq!(PitchClass::from_midi(-1), PitchClass::B);
+        assert_eq!(PitchClass::from_midi(-12), PitchClass::C);
+        assert_eq!(PitchClass::ASharp.index(), 10);
+    }
+
+    #[test]
+    fn note_frequency_folds_into_base_octave() {
+        let note = Note {
+            active: true,
+            id: 1.5,
+            dist: crate::NoteDists {
+                amp: 0.0,
+                mean: 0.0,
+                sigma: 0.0,
+                taken: false,
+            },
+            amplitude_out: 0.0,
+            amplitude_iir2: 0.0,
+            endured: 0,
+        };
+        assert!((note.frequency(100.0) - 100.0 * 2f32.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn octave_from_loudest_bin() {
+        let bins = 4;
+        let mut outbins = vec![0.0; bins * 3];
+        outbins[bins + 2] = 1.0;
+        assert_eq!(octaves_above_base(&outbins, bins, 0.5), 1.5);
+    }
+
+    #[test]
+    fn position_rounding_up_uses_next_octave() {
+        let bins = 4;
+        let mut outbins = vec![0.0; bins * 3];
+        // Energy in bin 0 of octave 2 belongs to a note just below it
+        outbins[bins * 2] = 1.0;
+        outbins[bins + 3] = 0.5;
+        let octaves = octaves_above_base(&outbins, bins, 0.95);
+        assert!((octaves - 1.95).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{MAX_SAMPLE_RATE, MIN_SAMPLE_RATE, NoteFinderError, Notefinder};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Number of fractional positions the filter kernel is tabulated for
+const PHASES: usize = 256;
+
+/// Trade-off between resampling cost and aliasing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResampleQuality {
+    /// Linear interpolation, cheap but aliases when downsampling
+    Fast,
+    /// Windowed sinc with 8 zero crossings
+    #[default]
+    Balanced,
+    /// Windowed sinc with 32 zero crossings
+    High,
+}
+
+/// Streaming sample rate converter.
+///
+/// Samples can be provided in blocks of any size, the filter state is kept between calls.
+pub struct Resampler {
+    input_rate: i32,
+    output_rate: i32,
+    quality: ResampleQuality,
+    /// Input samples advanced per output sample
+    step: f64,
+    /// Taps on each side of the interpolation point
+    half: usize,
+    /// `PHASES + 1` rows of `2 * half` taps
+    table: Vec<f32>,
+    history: Vec<f32>,
+    /// Position of the next output sample in `history`
+    position: f64,
+}
+
+impl Resampler {
+    /// Create a resampler converting from `input_rate` to `output_rate`.
+    ///
+    /// Both rates have to be within `MIN_SAMPLE_RATE` and `MAX_SAMPLE_RATE`.
+    pub fn new(
+        input_rate: i32,
+        output_rate: i32,
+        quality: ResampleQuality,
+    ) -> Result<Resampler, NoteFinderError> {
+        for rate in [input_rate, output_rate] {
+            if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&rate) {
+                return Err(NoteFinderError::UnsupportedSampleRate {
+                    expected_min: MIN_SAMPLE_RATE,
+                    expected_max: MAX_SAMPLE_RATE,
+                    found: rate,
+                });
+            }
+        }
+
+        let step = input_rate as f64 / output_rate as f64;
+        // Lower the cutoff below the output nyquist frequency when downsampling
+        let cutoff = (1.0 / step).min(1.0);
+
+        let (half, kernel): (usize, Box<dyn Fn(f64) -> f64>) = match quality {
+            ResampleQuality::Fast => (1, Box::new(|t: f64| (1.0 - t.abs()).max(0.0))),
+            ResampleQuality::Balanced | ResampleQuality::High => {
+                let zero_crossings = if quality == ResampleQuality::High {
+                    32.0
+                } else {
+                    8.0
+                };
+                let width = zero_crossings / cutoff;
+                (
+                    width.ceil() as usize,
+                    Box::new(move |t: f64| {
+                        if t.abs() >= width {
+                            return 0.0;
+                        }
+                        let x = PI * t / width;
+                        let blackman = 0.42 + 0.5 * x.cos() + 0.08 * (2.0 * x).cos();
+                        let sinc = if t == 0.0 {
+                            1.0
+                        } else {
+                            (PI * cutoff * t).sin() / (PI * cutoff * t)
+                        };
+                        sinc * blackman
+                    }),
+                )
+            }
+        };
+
+        let taps = 2 * half;
+        let mut table = vec![0.0; (PHASES + 1) * taps];
+        for (phase, row) in table.chunks_exact_mut(taps).enumerate() {
+            let frac = phase as f64 / PHASES as f64;
+            let weights: Vec<f64> = (0..taps)
+                .map(|j| kernel(j as f64 - (half as f64 - 1.0) - frac))
+                .collect();
+            // Normalize every phase to unity gain
+            let sum: f64 = weights.iter().sum();
+            for (r, w) in row.iter_mut().zip(weights) {
+                *r = (w / sum) as f32;
+            }
+        }
+
+        Ok(Resampler {
+            input_rate,
+            output_rate,
+            quality,
+            step,
+            half,
+            table,
+            history: vec![0.0; half - 1],
+            position: (half - 1) as f64,
+        })
+    }
+
+    pub fn input_rate(&self) -> i32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> i32 {
+        self.output_rate
+    }
+
+    pub fn quality(&self) -> ResampleQuality {
+        self.quality
+    }
+
+    /// Convert a block of samples, the resampled samples are appended to `out`
+    pub fn process(&mut self, input: impl IntoIterator<Item = f32>, out: &mut Vec<f32>) {
+        self.history.extend(input);
+
+        let taps = 2 * self.half;
+        while self.position as usize + self.half < self.history.len() {
+            let index = self.position as usize;
+            let phase = (self.position - index as f64) * PHASES as f64;
+            let row = phase as usize;
+            let t = (phase - row as f64) as f32;
+
+            let a = &self.table[row * taps..(row + 1) * taps];
+            let b = &self.table[(row + 1) * taps..(row + 2) * taps];
+            let start = index + 1 - self.half;
+            let window = &self.history[start..start + taps];
+
+            let sample = window
+                .iter()
+                .zip(a.iter().zip(b))
+                .map(|(x, (a, b))| x * (a + (b - a) * t))
+                .sum();
+            out.push(sample);
+            self.position += self.step;
+        }
+
+        // Drop the samples no longer needed by the next output sample
+        let consumed = (self.position as usize + 1)
+            .saturating_sub(self.half)
+            .min(self.history.len());
+        self.history.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.history.resize(self.half - 1, 0.0);
+        self.position = (self.half - 1) as f64;
+    }
+}
+
+impl Notefinder {
+    /// Create a notefinder analyzing at `samplerate` that accepts audio at `input_rate`.
+    ///
+    /// Pushed samples are resampled before they enter the ringbuffer, so settings tuned
+    /// for one samplerate behave the same for every sound card.
+    pub fn with_input_rate(
+        samplerate: i32,
+        input_rate: i32,
+        quality: ResampleQuality,
+    ) -> Result<Notefinder, NoteFinderError> {
+        let mut notefinder = Notefinder::try_new(samplerate)?;
+        notefinder.set_input_rate(input_rate, quality)?;
+        Ok(notefinder)
+    }
+
+    /// Change the samplerate of the pushed samples.
+    ///
+    /// Resampling is disabled when the input rate matches the notefinder samplerate.
+    pub fn set_input_rate(
+        &mut self,
+        input_rate: i32,
+        quality: ResampleQuality,
+    ) -> Result<(), NoteFinderError> {
+        self.resampler = if input_rate == self.samplerate {
+            None
+        } else {
+            Some(Resampler::new(input_rate, self.samplerate, quality)?)
+        };
+        Ok(())
+    }
+
+    /// Samplerate of the pushed samples
+    pub fn input_rate(&self) -> i32 {
+        self.resampler
+            .as_ref()
+            .map_or(self.samplerate, Resampler::input_rate)
+    }
+
+    /// Write samples at the input rate into the ringbuffer, resampling them if needed
+    pub(crate) fn write_samples(&mut self, samples: impl IntoIterator<Item = f32>) {
+        match &mut self.resampler {
+            Some(resampler) => {
+                self.resampled.clear();
+                resampler.process(samples, &mut self.resampled);
+                self.buffer.push(&self.resampled);
+            }
+            None => self.buffer.push_iter(samples),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: i32, frequency: f32, len: usize) -> impl Iterator<Item = f32> {
+        (0..len).map(move |i| (std::f32::consts::TAU * frequency * i as f32 / rate as f32).sin())
+    }
+
+    /// Estimate the frequency of a signal from its rising zero crossings
+    fn frequency(samples: &[f32], rate: i32) -> f32 {
+        let crossings: Vec<usize> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+            .map(|(i, _)| i)
+            .collect();
+        let periods = (crossings.len() - 1) as f32;
+        periods * rate as f32 / (crossings[crossings.len() - 1] - crossings[0]) as f32
+    }
+
+    #[test]
+    fn output_length_follows_ratio() {
+        for quality in [
+            ResampleQuality::Fast,
+            ResampleQuality::Balanced,
+            ResampleQuality::High,
+        ] {
+            let mut resampler = Resampler::new(44100, 16000, quality).unwrap();
+            let mut out = Vec::new();
+            resampler.process(std::iter::repeat_n(0.0, 44100), &mut out);
+            assert!(
+                (out.len() as i32 - 16000).abs() <= 40,
+                "{quality:?}: {}",
+                out.len()
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_dc_level() {
+        let mut resampler = Resampler::new(48000, 22050, ResampleQuality::Balanced).unwrap();
+        let mut out = Vec::new();
+        resampler.process(std::iter::repeat_n(0.5, 4800), &mut out);
+        for sample in &out[100..] {
+            assert!((sample - 0.5).abs() < 1e-3, "{sample}");
+        }
+    }
+
+    #[test]
+    fn keeps_frequency() {
+        let mut resampler = Resampler::new(48000, 16000, ResampleQuality::Balanced).unwrap();
+        let mut out = Vec::new();
+        resampler.process(sine(48000, 440.0, 48000), &mut out);
+        assert!((frequency(&out, 16000) - 440.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn block_size_doesnt_change_output() {
+        let input: Vec<f32> = sine(44100, 1000.0, 8000).collect();
+
+        let mut whole = Vec::new();
+        Resampler::new(44100, 48000, ResampleQuality::High)
+            .unwrap()
+            .process(input.iter().copied(), &mut whole);
+
+        let mut blocks = Vec::new();
+        let mut resampler = Resampler::new(44100, 48000, ResampleQuality::High).unwrap();
+        for block in input.chunks(123) {
+            resampler.process(block.iter().copied(), &mut blocks);
+        }
+
+        assert_eq!(whole.len(), blocks.len());
+        for (a, b) in whole.iter().zip(&blocks) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn notefinder_input_rate() {
+        let mut notefinder =
+            Notefinder::with_input_rate(16000, 48000, ResampleQuality::Fast).unwrap();
+        assert_eq!(notefinder.input_rate(), 48000);
+        assert_eq!(notefinder.samplerate(), 16000);
+
+        notefinder.push_samples(&[0.0; 4800]);
+        assert!((notefinder.timestamp() - 0.1).abs() < 0.01);
+
+        notefinder
+            .set_input_rate(16000, ResampleQuality::Fast)
+            .unwrap();
+        assert!(notefinder.resampler.is_none());
+        assert!(notefinder.set_input_rate(1, ResampleQuality::Fast).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_rates() {
+        assert!(Resampler::new(0, 16000, ResampleQuality::Fast).is_err());
+        assert!(Resampler::new(48000, MIN_SAMPLE_RATE - 1, ResampleQuality::Fast).is_err());
+        assert!(Resampler::new(MAX_SAMPLE_RATE + 1, 48000, ResampleQuality::High).is_err());
+        assert!(Resampler::new(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE, ResampleQuality::High).is_ok());
+    }
+}
@@ -0,0 +1,151 @@
+/// Number of samples held by the ringbuffer, matching the sound buffer in colorchord's `main.c`.
+pub const RINGBUFFER_SIZE: usize = 8096;
+
+/// Fixed size sample buffer that the notefinder reads from.
+///
+/// The C notefinder keeps track of where it last stopped reading and consumes samples
+/// up to the write head, so the buffer is never re-linearized.
+///
+/// At most `size - 1` unread samples are kept. If the head caught up with the read
+/// position the notefinder would see no new samples at all, so on overflow the buffer
+/// is rotated to hold the newest `size - 1` samples after the read position.
+pub(crate) struct Ringbuffer {
+    buffer: Box<[f32]>,
+    head: usize,
+    /// Head at the last `mark_read`, where the notefinder continues reading
+    read: usize,
+    /// Samples written since the last `mark_read`, at most `size - 1`
+    unread: usize,
+    /// Samples written since creation
+    written: u64,
+}
+
+impl Ringbuffer {
+    pub fn new(size: usize) -> Ringbuffer {
+        Ringbuffer {
+            buffer: vec![0.0; size].into_boxed_slice(),
+            head: 0,
+            read: 0,
+            unread: 0,
+            written: 0,
+        }
+    }
+
+    /// Write samples at the head, wrapping around at the end of the buffer.
+    ///
+    /// If more samples than the buffer can hold are provided only the most recent ones are kept.
+    pub fn push(&mut self, mut input: &[f32]) {
+        let size = self.buffer.len();
+        self.written += input.len() as u64;
+        self.unread += input.len();
+        if input.len() > size {
+            input = &input[input.len() - size..];
+        }
+
+        let first_len = input.len().min(size - self.head);
+        self.buffer[self.head..self.head + first_len].copy_from_slice(&input[..first_len]);
+        self.buffer[..input.len() - first_len].copy_from_slice(&input[first_len..]);
+
+        self.head = (self.head + input.len()) % size;
+        self.limit_unread();
+    }
+
+    /// Write samples one at a time, used when samples are converted on the fly
+    pub fn push_iter(&mut self, input: impl IntoIterator<Item = f32>) {
+        for sample in input {
+            self.buffer[self.head] = sample;
+            self.head = (self.head + 1) % self.buffer.len();
+            self.written += 1;
+            self.unread += 1;
+        }
+        self.limit_unread();
+    }
+
+    /// Rotate the newest `size - 1` samples to start at the read position after an overflow
+    fn limit_unread(&mut self) {
+        let size = self.buffer.len();
+        if self.unread < size {
+            return;
+        }
+
+        // The newest sample sits before the head and has to end up before the new head
+        let head = (self.read + size - 1) % size;
+        self.buffer.rotate_right((head + size - self.head) % size);
+        self.head = head;
+        self.unread = size - 1;
+    }
+
+    /// Mark everything up to the head as read, called after the notefinder ran
+    pub fn mark_read(&mut self) {
+        self.read = self.head;
+        self.unread = 0;
+    }
+
+    /// Position the next sample will be written to
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// Total number of samples written, including the ones already overwritten
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples from the read position up to the head, in the order the notefinder sees them
+    fn unread(buffer: &Ringbuffer) -> Vec<f32> {
+        let size = buffer.as_slice().len();
+        let count = (buffer.head() + size - buffer.read) % size;
+        (0..count)
+            .map(|i| buffer.as_slice()[(buffer.read + i) % size])
+            .collect()
+    }
+
+    #[test]
+    fn push_wraps_around() {
+        let mut buffer = Ringbuffer::new(8);
+        buffer.push(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        buffer.mark_read();
+        buffer.push(&[6.0, 7.0, 8.0, 9.0]);
+
+        assert_eq!(buffer.head(), 1);
+        assert_eq!(unread(&buffer), [6.0, 7.0, 8.0, 9.0]);
+        assert_eq!(buffer.written(), 9);
+    }
+
+    #[test]
+    fn push_iter_matches_push() {
+        let mut a = Ringbuffer::new(8);
+        let mut b = Ringbuffer::new(8);
+        let samples: Vec<f32> = (0..13).map(|i| i as f32).collect();
+        a.push(&samples);
+        b.push_iter(samples.iter().copied());
+
+        assert_eq!(a.head(), b.head());
+        assert_eq!(a.as_slice(), b.as_slice());
+    }
+
+    #[test]
+    fn overflow_keeps_newest_unread_samples() {
+        let mut buffer = Ringbuffer::new(8);
+        buffer.push(&[0.0; 3]);
+        buffer.mark_read();
+
+        // A multiple of the size would otherwise leave the head at the read position
+        let samples: Vec<f32> = (1..=16).map(|i| i as f32).collect();
+        buffer.push(&samples);
+        assert_ne!(buffer.head(), buffer.read);
+        assert_eq!(unread(&buffer), samples[9..]);
+
+        buffer.push_iter([17.0, 18.0]);
+        assert_eq!(unread(&buffer), [12.0, 13.0, 14.0, 15.0, 16.0, 17.0, 18.0]);
+    }
+}
@@ -0,0 +1,140 @@
+use super::{ByteOrder, LedSink, RateLimiter, bind_for, resolve};
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+
+/// Default DDP port
+pub const DDP_PORT: u16 = 4048;
+
+const DDP_HEADER_LEN: usize = 10;
+/// Largest amount of pixel data per packet, 480 RGB pixels
+const DDP_MAX_DATA: usize = 1440;
+const DDP_FLAG_VERSION_1: u8 = 0x40;
+const DDP_FLAG_PUSH: u8 = 0x01;
+/// RGB with 8 bits per channel
+const DDP_TYPE_RGB8: u8 = 0x0b;
+
+/// Options for `DdpSink`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DdpOptions {
+    /// Destination id, 1 is the default output device
+    pub destination: u8,
+    pub byte_order: ByteOrder,
+    /// Maximum number of frames sent per second
+    pub max_fps: Option<f32>,
+}
+
+impl Default for DdpOptions {
+    fn default() -> DdpOptions {
+        DdpOptions {
+            destination: 1,
+            byte_order: ByteOrder::Rgb,
+            max_fps: None,
+        }
+    }
+}
+
+/// Sends LED frames using the Distributed Display Protocol, as used by WLED.
+///
+/// Frames are split into packets of 480 pixels, the last packet of a frame has the push flag set.
+pub struct DdpSink {
+    socket: UdpSocket,
+    options: DdpOptions,
+    limiter: RateLimiter,
+    sequence: u8,
+    data: Vec<u8>,
+}
+
+impl DdpSink {
+    pub fn connect<A: ToSocketAddrs>(addr: A, options: DdpOptions) -> io::Result<DdpSink> {
+        let addr = resolve(addr)?;
+        let socket = bind_for(&addr)?;
+        socket.connect(addr)?;
+
+        Ok(DdpSink {
+            socket,
+            limiter: RateLimiter::new(options.max_fps),
+            options,
+            sequence: 0,
+            data: Vec::new(),
+        })
+    }
+
+    pub fn options(&self) -> &DdpOptions {
+        &self.options
+    }
+}
+
+impl LedSink for DdpSink {
+    fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()> {
+        if !self.limiter.ready() {
+            return Ok(());
+        }
+
+        // Sequence numbers run from 1 to 15, 0 means unused
+        self.sequence = self.sequence % 15 + 1;
+
+        self.data.clear();
+        for led in leds {
+            self.data
+                .extend_from_slice(&self.options.byte_order.apply(*led));
+        }
+
+        let chunks = self.data.len().div_ceil(DDP_MAX_DATA).max(1);
+        let mut packet = Vec::with_capacity(DDP_HEADER_LEN + DDP_MAX_DATA);
+        for i in 0..chunks {
+            let offset = i * DDP_MAX_DATA;
+            let chunk = &self.data[offset..self.data.len().min(offset + DDP_MAX_DATA)];
+
+            let mut flags = DDP_FLAG_VERSION_1;
+            if i == chunks - 1 {
+                flags |= DDP_FLAG_PUSH;
+            }
+
+            packet.clear();
+            packet.push(flags);
+            packet.push(self.sequence);
+            packet.push(DDP_TYPE_RGB8);
+            packet.push(self.options.destination);
+            packet.extend_from_slice(&(offset as u32).to_be_bytes());
+            packet.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            packet.extend_from_slice(chunk);
+            self.socket.send(&packet)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn splits_frames_and_pushes_the_last_packet() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let options = DdpOptions {
+            byte_order: ByteOrder::Grb,
+            ..Default::default()
+        };
+        let mut sink = DdpSink::connect(receiver.local_addr().unwrap(), options).unwrap();
+
+        let mut leds = vec![[0; 3]; 481];
+        leds[480] = [1, 2, 3];
+        sink.send(&leds).unwrap();
+
+        let mut buf = [0; 2048];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(len, DDP_HEADER_LEN + DDP_MAX_DATA);
+        assert_eq!(&buf[..10], &[0x40, 1, 0x0b, 1, 0, 0, 0, 0, 0x05, 0xa0]);
+
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[0x41, 1, 0x0b, 1, 0, 0, 0x05, 0xa0, 0, 3, 2, 1, 3]
+        );
+    }
+}
@@ -0,0 +1,544 @@
+use super::{ByteOrder, LedSink, RateLimiter, bind_for, resolve};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+
+/// Default E1.31 (sACN) port
+pub const E131_PORT: u16 = 5568;
+/// Default Art-Net port
+pub const ARTNET_PORT: u16 = 6454;
+/// Number of RGB pixels that fit in a 512 channel DMX universe
+pub const PIXELS_PER_UNIVERSE: usize = 170;
+
+const ACN_PACKET_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_ROOT_E131_EXTENDED: u32 = 0x0000_0008;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_E131_EXTENDED_SYNCHRONIZATION: u32 = 0x0000_0001;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+const ARTNET_OP_SYNC: u16 = 0x5200;
+const ARTNET_PROTOCOL_VERSION: u16 = 14;
+
+/// Highest E1.31 data universe
+const E131_MAX_UNIVERSE: u16 = 63999;
+/// Art-Net port-addresses are 15 bits
+const ARTNET_MAX_UNIVERSE: u16 = 0x7fff;
+
+/// Pack LEDs into DMX channels and split them into universes at pixel boundaries
+fn universes(leds: &[[u8; 3]], byte_order: ByteOrder) -> impl Iterator<Item = Vec<u8>> + '_ {
+    leds.chunks(PIXELS_PER_UNIVERSE).map(move |chunk| {
+        chunk
+            .iter()
+            .flat_map(|led| byte_order.apply(*led))
+            .collect()
+    })
+}
+
+/// Universe of the last pixel in `leds` when the first pixel is on `start_universe`
+fn last_universe(start_universe: u16, leds: &[[u8; 3]], max_universe: u16) -> io::Result<u16> {
+    let extra = leds.len().saturating_sub(1) / PIXELS_PER_UNIVERSE;
+    u16::try_from(extra)
+        .ok()
+        .and_then(|extra| start_universe.checked_add(extra))
+        .filter(|last| *last <= max_universe)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} LEDs starting at universe {} go past universe {}",
+                    leds.len(),
+                    start_universe,
+                    max_universe
+                ),
+            )
+        })
+}
+
+/// Write the flags and length field of an ACN PDU starting at `start`
+fn pdu_length(packet: &mut [u8], start: usize) {
+    let length = (packet.len() - start) as u16 | 0x7000;
+    packet[start..start + 2].copy_from_slice(&length.to_be_bytes());
+}
+
+/// Random component identifier for a sender
+fn random_cid() -> [u8; 16] {
+    let mut cid = [0; 16];
+    for half in cid.chunks_mut(8) {
+        half.copy_from_slice(&RandomState::new().build_hasher().finish().to_be_bytes());
+    }
+    cid
+}
+
+/// Options for `E131Sink`
+#[derive(Debug, Clone, PartialEq)]
+pub struct E131Options {
+    /// Name of the source shown by receivers, at most 63 bytes are sent
+    pub source_name: String,
+    /// Component identifier, should stay the same for the lifetime of the sender
+    pub cid: [u8; 16],
+    /// Priority of the data (0 - 200), receivers follow the source with the highest priority
+    pub priority: u8,
+    /// Universe of the first 170 pixels, starting at 1. Frames that need universes past
+    /// 63999 are rejected
+    pub start_universe: u16,
+    /// Universe to send synchronization packets on after every frame, starting at 1
+    pub sync_universe: Option<u16>,
+    pub byte_order: ByteOrder,
+    /// Maximum number of frames sent per second
+    pub max_fps: Option<f32>,
+}
+
+impl Default for E131Options {
+    fn default() -> E131Options {
+        E131Options {
+            source_name: "rustchord".to_owned(),
+            cid: random_cid(),
+            priority: 100,
+            start_universe: 1,
+            sync_universe: None,
+            byte_order: ByteOrder::Rgb,
+            max_fps: None,
+        }
+    }
+}
+
+/// Sends LED frames as E1.31 (sACN) DMX universes.
+pub struct E131Sink {
+    socket: UdpSocket,
+    target: Option<SocketAddr>,
+    options: E131Options,
+    limiter: RateLimiter,
+    sequences: Vec<u8>,
+    sync_sequence: u8,
+}
+
+impl E131Sink {
+    /// Send every universe to a single receiver
+    pub fn unicast<A: ToSocketAddrs>(addr: A, options: E131Options) -> io::Result<E131Sink> {
+        E131Sink::validate(&options)?;
+        let addr = resolve(addr)?;
+        Ok(E131Sink::with_socket(bind_for(&addr)?, Some(addr), options))
+    }
+
+    /// Send every universe to its E1.31 multicast group
+    pub fn multicast(options: E131Options) -> io::Result<E131Sink> {
+        E131Sink::validate(&options)?;
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        Ok(E131Sink::with_socket(socket, None, options))
+    }
+
+    /// Universe 0 is reserved by E1.31, receivers drop packets sent to it
+    fn validate(options: &E131Options) -> io::Result<()> {
+        for (name, universe) in [
+            ("start_universe", Some(options.start_universe)),
+            ("sync_universe", options.sync_universe),
+        ] {
+            if universe == Some(0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} 0 is reserved by E1.31, universes start at 1", name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn with_socket(
+        socket: UdpSocket,
+        target: Option<SocketAddr>,
+        options: E131Options,
+    ) -> E131Sink {
+        E131Sink {
+            socket,
+            target,
+            limiter: RateLimiter::new(options.max_fps),
+            options,
+            sequences: Vec::new(),
+            sync_sequence: 0,
+        }
+    }
+
+    /// Multicast group of a universe, 239.255.x.y
+    pub fn multicast_addr(universe: u16) -> SocketAddr {
+        let [hi, lo] = universe.to_be_bytes();
+        SocketAddrV4::new(Ipv4Addr::new(239, 255, hi, lo), E131_PORT).into()
+    }
+
+    pub fn options(&self) -> &E131Options {
+        &self.options
+    }
+
+    fn send_to_universe(&self, packet: &[u8], universe: u16) -> io::Result<()> {
+        let target = self
+            .target
+            .unwrap_or_else(|| E131Sink::multicast_addr(universe));
+        self.socket.send_to(packet, target)?;
+        Ok(())
+    }
+
+    fn root_layer(&self, vector: u32) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(638);
+        packet.extend_from_slice(&0x0010u16.to_be_bytes());
+        packet.extend_from_slice(&0u16.to_be_bytes());
+        packet.extend_from_slice(ACN_PACKET_IDENTIFIER);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&vector.to_be_bytes());
+        packet.extend_from_slice(&self.options.cid);
+        packet
+    }
+
+    fn data_packet(&self, universe: u16, sequence: u8, data: &[u8]) -> Vec<u8> {
+        let mut packet = self.root_layer(VECTOR_ROOT_E131_DATA);
+
+        // Framing layer
+        let mut source_name = [0u8; 64];
+        let name = self.options.source_name.as_bytes();
+        let name_len = name.len().min(63);
+        source_name[..name_len].copy_from_slice(&name[..name_len]);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+        packet.extend_from_slice(&source_name);
+        packet.push(self.options.priority.min(200));
+        packet.extend_from_slice(&self.options.sync_universe.unwrap_or(0).to_be_bytes());
+        packet.push(sequence);
+        packet.push(0);
+        packet.extend_from_slice(&universe.to_be_bytes());
+
+        // DMP layer
+        packet.extend_from_slice(&[0, 0]);
+        packet.push(VECTOR_DMP_SET_PROPERTY);
+        packet.push(0xa1);
+        packet.extend_from_slice(&0u16.to_be_bytes());
+        packet.extend_from_slice(&1u16.to_be_bytes());
+        packet.extend_from_slice(&(data.len() as u16 + 1).to_be_bytes());
+        packet.push(0);
+        packet.extend_from_slice(data);
+
+        pdu_length(&mut packet, 16);
+        pdu_length(&mut packet, 38);
+        pdu_length(&mut packet, 115);
+        packet
+    }
+
+    fn sync_packet(&self, sync_universe: u16, sequence: u8) -> Vec<u8> {
+        let mut packet = self.root_layer(VECTOR_ROOT_E131_EXTENDED);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&VECTOR_E131_EXTENDED_SYNCHRONIZATION.to_be_bytes());
+        packet.push(sequence);
+        packet.extend_from_slice(&sync_universe.to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        pdu_length(&mut packet, 16);
+        pdu_length(&mut packet, 38);
+        packet
+    }
+}
+
+impl LedSink for E131Sink {
+    fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()> {
+        if !self.limiter.ready() {
+            return Ok(());
+        }
+
+        last_universe(self.options.start_universe, leds, E131_MAX_UNIVERSE)?;
+        for (i, data) in universes(leds, self.options.byte_order).enumerate() {
+            if self.sequences.len() <= i {
+                self.sequences.push(0);
+            }
+            let sequence = self.sequences[i];
+            self.sequences[i] = sequence.wrapping_add(1);
+
+            let universe = self.options.start_universe + i as u16;
+            let packet = self.data_packet(universe, sequence, &data);
+            self.send_to_universe(&packet, universe)?;
+        }
+
+        if let Some(sync_universe) = self.options.sync_universe {
+            let packet = self.sync_packet(sync_universe, self.sync_sequence);
+            self.sync_sequence = self.sync_sequence.wrapping_add(1);
+            self.send_to_universe(&packet, sync_universe)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Options for `ArtNetSink`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtNetOptions {
+    /// Port-address (net, sub-net and universe) of the first 170 pixels, frames that need
+    /// port-addresses past 32767 are rejected
+    pub start_universe: u16,
+    /// Send an ArtSync packet after every frame
+    pub sync: bool,
+    pub byte_order: ByteOrder,
+    /// Maximum number of frames sent per second
+    pub max_fps: Option<f32>,
+}
+
+impl Default for ArtNetOptions {
+    fn default() -> ArtNetOptions {
+        ArtNetOptions {
+            start_universe: 0,
+            sync: false,
+            byte_order: ByteOrder::Rgb,
+            max_fps: None,
+        }
+    }
+}
+
+/// Sends LED frames as Art-Net ArtDmx packets.
+pub struct ArtNetSink {
+    socket: UdpSocket,
+    options: ArtNetOptions,
+    limiter: RateLimiter,
+    sequence: u8,
+}
+
+impl ArtNetSink {
+    /// Send to a node or a broadcast address, the Art-Net port is 6454
+    pub fn connect<A: ToSocketAddrs>(addr: A, options: ArtNetOptions) -> io::Result<ArtNetSink> {
+        let addr = resolve(addr)?;
+        let socket = bind_for(&addr)?;
+        socket.set_broadcast(true)?;
+        socket.connect(addr)?;
+
+        Ok(ArtNetSink {
+            socket,
+            limiter: RateLimiter::new(options.max_fps),
+            options,
+            sequence: 0,
+        })
+    }
+
+    pub fn options(&self) -> &ArtNetOptions {
+        &self.options
+    }
+
+    fn header(opcode: u16) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(530);
+        packet.extend_from_slice(ARTNET_ID);
+        packet.extend_from_slice(&opcode.to_le_bytes());
+        packet.extend_from_slice(&ARTNET_PROTOCOL_VERSION.to_be_bytes());
+        packet
+    }
+
+    fn dmx_packet(sequence: u8, universe: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = ArtNetSink::header(ARTNET_OP_DMX);
+        packet.push(sequence);
+        packet.push(0);
+        packet.extend_from_slice(&(universe & ARTNET_MAX_UNIVERSE).to_le_bytes());
+
+        // The data length has to be even
+        let length = data.len() + data.len() % 2;
+        packet.extend_from_slice(&(length as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+        packet.resize(packet.len() + length - data.len(), 0);
+        packet
+    }
+
+    fn sync_packet() -> Vec<u8> {
+        let mut packet = ArtNetSink::header(ARTNET_OP_SYNC);
+        packet.extend_from_slice(&[0, 0]);
+        packet
+    }
+}
+
+impl LedSink for ArtNetSink {
+    fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()> {
+        if !self.limiter.ready() {
+            return Ok(());
+        }
+
+        last_universe(self.options.start_universe, leds, ARTNET_MAX_UNIVERSE)?;
+
+        // Sequence 0 disables reordering on the receiver, so wrap from 255 to 1
+        self.sequence = self.sequence % 255 + 1;
+
+        for (i, data) in universes(leds, self.options.byte_order).enumerate() {
+            let universe = self.options.start_universe + i as u16;
+            self.socket
+                .send(&ArtNetSink::dmx_packet(self.sequence, universe, &data))?;
+        }
+
+        if self.options.sync {
+            self.socket.send(&ArtNetSink::sync_packet())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn receiver() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn e131_options() -> E131Options {
+        E131Options {
+            source_name: "test".to_owned(),
+            cid: std::array::from_fn(|i| i as u8 + 1),
+            priority: 150,
+            sync_universe: Some(7),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn e131_data_packet_layers() {
+        let sink = E131Sink::unicast("127.0.0.1:5568", e131_options()).unwrap();
+        let packet = sink.data_packet(0x0102, 9, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(packet.len(), 126 + 6);
+
+        // Root layer
+        assert_eq!(&packet[0..4], &[0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(&packet[4..16], ACN_PACKET_IDENTIFIER);
+        assert_eq!(&packet[16..18], &(0x7000u16 | (132 - 16)).to_be_bytes());
+        assert_eq!(&packet[18..22], &[0, 0, 0, 4]);
+        assert_eq!(&packet[22..38], &e131_options().cid);
+
+        // Framing layer
+        assert_eq!(&packet[38..40], &(0x7000u16 | (132 - 38)).to_be_bytes());
+        assert_eq!(&packet[40..44], &[0, 0, 0, 2]);
+        assert_eq!(&packet[44..48], b"test");
+        assert!(packet[48..108].iter().all(|b| *b == 0));
+        assert_eq!(packet[108], 150);
+        assert_eq!(&packet[109..111], &[0, 7]);
+        assert_eq!(packet[111], 9);
+        assert_eq!(packet[112], 0);
+        assert_eq!(&packet[113..115], &[0x01, 0x02]);
+
+        // DMP layer
+        assert_eq!(&packet[115..117], &(0x7000u16 | (132 - 115)).to_be_bytes());
+        assert_eq!(&packet[117..119], &[0x02, 0xa1]);
+        assert_eq!(&packet[119..123], &[0, 0, 0, 1]);
+        assert_eq!(&packet[123..125], &[0, 7]);
+        assert_eq!(packet[125], 0);
+        assert_eq!(&packet[126..], &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn e131_sync_packet() {
+        let sink = E131Sink::unicast("127.0.0.1:5568", e131_options()).unwrap();
+        let packet = sink.sync_packet(7, 3);
+        assert_eq!(packet.len(), 49);
+        assert_eq!(&packet[16..18], &(0x7000u16 | (49 - 16)).to_be_bytes());
+        assert_eq!(&packet[18..22], &[0, 0, 0, 8]);
+        assert_eq!(&packet[38..40], &(0x7000u16 | (49 - 38)).to_be_bytes());
+        assert_eq!(&packet[40..44], &[0, 0, 0, 1]);
+        assert_eq!(&packet[44..], &[3, 0, 7, 0, 0]);
+    }
+
+    #[test]
+    fn e131_splits_universes() {
+        let receiver = receiver();
+        let options = E131Options {
+            start_universe: 4,
+            sync_universe: None,
+            ..e131_options()
+        };
+        let mut sink = E131Sink::unicast(receiver.local_addr().unwrap(), options).unwrap();
+        sink.send(&[[1, 2, 3]; PIXELS_PER_UNIVERSE + 1]).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(len, 126 + PIXELS_PER_UNIVERSE * 3);
+        assert_eq!(&buf[113..115], &[0, 4]);
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(len, 126 + 3);
+        assert_eq!(&buf[113..115], &[0, 5]);
+    }
+
+    #[test]
+    fn e131_rejects_universe_overflow() {
+        let options = E131Options {
+            start_universe: E131_MAX_UNIVERSE,
+            ..e131_options()
+        };
+        let receiver = receiver();
+        let mut sink = E131Sink::unicast(receiver.local_addr().unwrap(), options).unwrap();
+        // The last universe still fits
+        sink.send(&[[0; 3]; PIXELS_PER_UNIVERSE]).unwrap();
+        let err = sink.send(&[[0; 3]; PIXELS_PER_UNIVERSE + 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn e131_rejects_universe_zero() {
+        for options in [
+            E131Options {
+                start_universe: 0,
+                ..e131_options()
+            },
+            E131Options {
+                sync_universe: Some(0),
+                ..e131_options()
+            },
+        ] {
+            let err = E131Sink::unicast("127.0.0.1:5568", options.clone())
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(E131Sink::multicast(options).is_err());
+        }
+    }
+
+    #[test]
+    fn artdmx_header() {
+        let packet = ArtNetSink::dmx_packet(5, 0x1234, &[1, 2, 3]);
+        assert_eq!(&packet[0..8], b"Art-Net\0");
+        assert_eq!(&packet[8..10], &[0x00, 0x50]);
+        assert_eq!(&packet[10..12], &[0, 14]);
+        assert_eq!(packet[12], 5);
+        assert_eq!(packet[13], 0);
+        assert_eq!(&packet[14..16], &[0x34, 0x12]);
+        // Odd lengths are padded to an even number of channels
+        assert_eq!(&packet[16..18], &[0, 4]);
+        assert_eq!(&packet[18..], &[1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn artnet_sends_sequence_and_sync() {
+        let receiver = receiver();
+        let options = ArtNetOptions {
+            start_universe: 2,
+            sync: true,
+            byte_order: ByteOrder::Grb,
+            max_fps: None,
+        };
+        let mut sink = ArtNetSink::connect(receiver.local_addr().unwrap(), options).unwrap();
+        sink.send(&[[1, 2, 3], [4, 5, 6]]).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(buf[12], 1);
+        assert_eq!(&buf[14..16], &[2, 0]);
+        assert_eq!(&buf[18..len], &[2, 1, 3, 5, 4, 6]);
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"Art-Net\0\x00\x52\x00\x0e\x00\x00");
+    }
+
+    #[test]
+    fn artnet_rejects_universe_overflow() {
+        let receiver = receiver();
+        let options = ArtNetOptions {
+            start_universe: ARTNET_MAX_UNIVERSE,
+            ..Default::default()
+        };
+        let mut sink = ArtNetSink::connect(receiver.local_addr().unwrap(), options).unwrap();
+        let err = sink.send(&[[0; 3]; PIXELS_PER_UNIVERSE + 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,103 @@
+//! Sending LED colors to fixtures.
+//!
+//! Sinks take the colors produced by an `OutputMapper` and deliver them to hardware.
+
+mod ddp;
+mod dmx;
+mod network;
+mod opc;
+mod serial;
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+pub use ddp::{DDP_PORT, DdpOptions, DdpSink};
+pub use dmx::{
+    ARTNET_PORT, ArtNetOptions, ArtNetSink, E131_PORT, E131Options, E131Sink, PIXELS_PER_UNIVERSE,
+};
+pub use network::{NetworkDisplay, NetworkOptions};
+pub use opc::{OPC_PORT, OpcClient, OpcOptions};
+pub use serial::{AdalightSink, SerialOptions, Tpm2Sink, open_serial};
+
+/// Delivers frames of LED colors to a fixture.
+pub trait LedSink {
+    /// Send one frame of LED colors
+    fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()>;
+}
+
+/// Order the color channels are sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ByteOrder {
+    #[default]
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ByteOrder {
+    /// Reorder an RGB color
+    pub fn apply(self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        use ByteOrder::*;
+        match self {
+            Rgb => [r, g, b],
+            Rbg => [r, b, g],
+            Grb => [g, r, b],
+            Gbr => [g, b, r],
+            Brg => [b, r, g],
+            Bgr => [b, g, r],
+        }
+    }
+}
+
+/// First address `addr` resolves to
+fn resolve<A: ToSocketAddrs>(addr: A) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "address resolved to nothing"))
+}
+
+/// UDP socket on an ephemeral port of the same address family as `addr`
+fn bind_for(addr: &SocketAddr) -> io::Result<UdpSocket> {
+    let bind: SocketAddr = match addr {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    UdpSocket::bind(bind)
+}
+
+/// Drops frames that arrive faster than a maximum frame rate.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    last_frame: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Limit to `max_fps` frames per second, `None` lets every frame through
+    pub fn new(max_fps: Option<f32>) -> RateLimiter {
+        RateLimiter {
+            interval: max_fps
+                .filter(|fps| *fps > 0.0)
+                .map(|fps| Duration::from_secs_f32(1.0 / fps)),
+            last_frame: None,
+        }
+    }
+
+    /// Returns true if a frame may be sent now
+    pub fn ready(&mut self) -> bool {
+        let now = Instant::now();
+        if let (Some(interval), Some(last)) = (self.interval, self.last_frame)
+            && now.duration_since(last) < interval
+        {
+            return false;
+        }
+
+        self.last_frame = Some(now);
+        true
+    }
+}
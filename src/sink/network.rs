@@ -0,0 +1,180 @@
+use super::{ByteOrder, LedSink, RateLimiter, bind_for, resolve};
+use crate::conf::ColorchordConf;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+
+/// Options for `NetworkDisplay`, defaults match colorchord's `DisplayNetwork`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkOptions {
+    /// Bytes sent in front of the LED data
+    pub header: Vec<u8>,
+    pub byte_order: ByteOrder,
+    /// Number of LEDs at the start of the strip that are sent as black
+    pub skip_leds: usize,
+    /// Maximum number of frames sent per second
+    pub max_fps: Option<f32>,
+}
+
+impl Default for NetworkOptions {
+    fn default() -> NetworkOptions {
+        NetworkOptions {
+            header: vec![0],
+            byte_order: ByteOrder::Rgb,
+            skip_leds: 0,
+            max_fps: None,
+        }
+    }
+}
+
+/// Sends raw LED frames over UDP, compatible with colorchord's `DisplayNetwork` and the
+/// ESP8266 colorchord firmware.
+///
+/// Every frame is a single datagram containing the header followed by three bytes per LED.
+pub struct NetworkDisplay {
+    socket: UdpSocket,
+    options: NetworkOptions,
+    limiter: RateLimiter,
+    buffer: Vec<u8>,
+}
+
+impl NetworkDisplay {
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        options: NetworkOptions,
+    ) -> io::Result<NetworkDisplay> {
+        let addr = resolve(addr)?;
+        let socket = bind_for(&addr)?;
+        socket.connect(addr)?;
+
+        Ok(NetworkDisplay {
+            socket,
+            limiter: RateLimiter::new(options.max_fps),
+            options,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Connect using the `address`, `port`, `skipfirst`, `firstval` and `fliprg` keys of a
+    /// colorchord configuration
+    pub fn from_conf(conf: &ColorchordConf) -> io::Result<NetworkDisplay> {
+        fn number<T: FromStr>(conf: &ColorchordConf, key: &str, default: T) -> io::Result<T> {
+            conf.passthrough.get(key).map_or(Ok(default), |v| {
+                v.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid {}: {}", key, v),
+                    )
+                })
+            })
+        }
+
+        let address = conf
+            .passthrough
+            .get("address")
+            .map_or("192.168.4.1", String::as_str);
+        let port: u16 = number(conf, "port", 7777)?;
+        let firstval: u8 = number(conf, "firstval", 0)?;
+        let skipfirst: usize = number(conf, "skipfirst", 1)?;
+        let fliprg: i32 = number(conf, "fliprg", 0)?;
+        let options = NetworkOptions {
+            header: vec![firstval; skipfirst],
+            byte_order: if fliprg != 0 {
+                ByteOrder::Grb
+            } else {
+                ByteOrder::Rgb
+            },
+            ..Default::default()
+        };
+
+        NetworkDisplay::connect((address, port), options)
+    }
+
+    pub fn options(&self) -> &NetworkOptions {
+        &self.options
+    }
+
+    /// Address frames are sent to
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+}
+
+impl LedSink for NetworkDisplay {
+    fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()> {
+        if !self.limiter.ready() {
+            return Ok(());
+        }
+
+        let skip = self.options.skip_leds;
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&self.options.header);
+        self.buffer.resize(self.buffer.len() + skip * 3, 0);
+        for led in leds {
+            self.buffer
+                .extend_from_slice(&self.options.byte_order.apply(*led));
+        }
+
+        self.socket.send(&self.buffer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn receiver() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    #[test]
+    fn sends_header_and_leds() {
+        let receiver = receiver();
+        let options = NetworkOptions {
+            header: vec![0xaa, 0xbb],
+            byte_order: ByteOrder::Bgr,
+            skip_leds: 1,
+            max_fps: None,
+        };
+        let mut display = NetworkDisplay::connect(receiver.local_addr().unwrap(), options).unwrap();
+        display.send(&[[1, 2, 3], [4, 5, 6]]).unwrap();
+
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[0xaa, 0xbb, 0, 0, 0, 3, 2, 1, 6, 5, 4]);
+    }
+
+    #[test]
+    fn from_conf_reads_colorchord_keys() {
+        let receiver = receiver();
+        let port = receiver.local_addr().unwrap().port();
+        let conf: ColorchordConf = format!(
+            "address = 127.0.0.1\nport = {port}\nskipfirst = 3\nfirstval = 7\nfliprg = 1\n"
+        )
+        .parse()
+        .unwrap();
+
+        let mut display = NetworkDisplay::from_conf(&conf).unwrap();
+        assert_eq!(display.options().byte_order, ByteOrder::Grb);
+        display.send(&[[1, 2, 3]]).unwrap();
+
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[7, 7, 7, 2, 1, 3]);
+    }
+
+    #[test]
+    fn from_conf_rejects_values_that_dont_fit() {
+        for conf in ["port = 70000", "firstval = 256", "skipfirst = -1"] {
+            let conf: ColorchordConf = conf.parse().unwrap();
+            let err = NetworkDisplay::from_conf(&conf).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
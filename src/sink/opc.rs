@@ -0,0 +1,158 @@
+use super::{ByteOrder, LedSink, RateLimiter, resolve};
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// Default Open Pixel Control port
+pub const OPC_PORT: u16 = 7890;
+
+const OPC_SET_PIXEL_COLORS: u8 = 0;
+/// Largest number of pixels that fit in the 16 bit length of an OPC message
+const OPC_MAX_PIXELS: usize = u16::MAX as usize / 3;
+
+/// Options for `OpcClient`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcOptions {
+    /// Channel to address, 0 sends to every channel of the server
+    pub channel: u8,
+    pub byte_order: ByteOrder,
+    /// Minimum time between connection attempts after the connection was lost
+    pub reconnect_interval: Duration,
+    /// Longest time connecting or writing a frame may block before the connection is
+    /// dropped
+    pub timeout: Duration,
+    /// Maximum number of frames sent per second
+    pub max_fps: Option<f32>,
+}
+
+impl Default for OpcOptions {
+    fn default() -> OpcOptions {
+        OpcOptions {
+            channel: 0,
+            byte_order: ByteOrder::Rgb,
+            reconnect_interval: Duration::from_secs(1),
+            timeout: Duration::from_millis(500),
+            max_fps: None,
+        }
+    }
+}
+
+/// Sends LED frames to an Open Pixel Control server such as Fadecandy.
+///
+/// The connection is established on the first frame and reestablished when it's lost.
+/// Frames sent while disconnected return the connection error.
+pub struct OpcClient {
+    addr: SocketAddr,
+    stream: Option<TcpStream>,
+    options: OpcOptions,
+    limiter: RateLimiter,
+    last_attempt: Option<Instant>,
+    buffer: Vec<u8>,
+}
+
+impl OpcClient {
+    pub fn new<A: ToSocketAddrs>(addr: A, options: OpcOptions) -> io::Result<OpcClient> {
+        let addr = resolve(addr)?;
+
+        Ok(OpcClient {
+            addr,
+            stream: None,
+            limiter: RateLimiter::new(options.max_fps),
+            options,
+            last_attempt: None,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn options(&self) -> &OpcOptions {
+        &self.options
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn stream(&mut self) -> io::Result<&mut TcpStream> {
+        if self.stream.is_none() {
+            if let Some(last) = self.last_attempt
+                && last.elapsed() < self.options.reconnect_interval
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "waiting to reconnect to OPC server",
+                ));
+            }
+
+            self.last_attempt = Some(Instant::now());
+            let stream = TcpStream::connect_timeout(&self.addr, self.options.timeout)?;
+            stream.set_write_timeout(Some(self.options.timeout))?;
+            stream.set_nodelay(true)?;
+            self.stream = Some(stream);
+        }
+
+        Ok(self.stream.as_mut().unwrap())
+    }
+}
+
+impl LedSink for OpcClient {
+    fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()> {
+        if !self.limiter.ready() {
+            return Ok(());
+        }
+
+        let leds = &leds[..leds.len().min(OPC_MAX_PIXELS)];
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        buffer.push(self.options.channel);
+        buffer.push(OPC_SET_PIXEL_COLORS);
+        buffer.extend_from_slice(&((leds.len() * 3) as u16).to_be_bytes());
+        for led in leds {
+            buffer.extend_from_slice(&self.options.byte_order.apply(*led));
+        }
+
+        let result = self.stream().and_then(|stream| stream.write_all(&buffer));
+        self.buffer = buffer;
+        if result.is_err() {
+            self.stream = None;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn sends_set_pixel_colors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let options = OpcOptions {
+            channel: 2,
+            byte_order: ByteOrder::Grb,
+            ..Default::default()
+        };
+        let mut client = OpcClient::new(listener.local_addr().unwrap(), options).unwrap();
+        client.send(&[[1, 2, 3], [4, 5, 6]]).unwrap();
+        assert!(client.is_connected());
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut message = [0; 10];
+        stream.read_exact(&mut message).unwrap();
+        assert_eq!(message, [2, 0, 0, 6, 2, 1, 3, 5, 4, 6]);
+    }
+
+    #[test]
+    fn waits_before_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut client = OpcClient::new(addr, OpcOptions::default()).unwrap();
+        assert!(client.send(&[[0; 3]]).is_err());
+        let err = client.send(&[[0; 3]]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+}
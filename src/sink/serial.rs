@@ -0,0 +1,233 @@
+use super::{ByteOrder, LedSink, RateLimiter};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+const TPM2_FRAME_START: u8 = 0xc9;
+const TPM2_DATA_FRAME: u8 = 0xda;
+const TPM2_FRAME_END: u8 = 0x36;
+
+/// Options shared by the serial LED sinks
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SerialOptions {
+    pub byte_order: ByteOrder,
+    /// Maximum number of frames sent per second
+    pub max_fps: Option<f32>,
+}
+
+/// Open a serial device for writing LED frames.
+///
+/// On unix the port is switched to raw mode at `baud_rate`, supported rates are 9600, 19200,
+/// 38400, 57600, 115200 and 230400. On other platforms the baud rate has to be configured
+/// beforehand.
+pub fn open_serial<P: AsRef<Path>>(path: P, baud_rate: u32) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOCTTY);
+    }
+
+    let file = options.open(path)?;
+    configure_serial(&file, baud_rate)?;
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn configure_serial(file: &File, baud_rate: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let speed = match baud_rate {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate {}", baud_rate),
+            ));
+        }
+    };
+
+    let fd = file.as_raw_fd();
+    unsafe {
+        let mut tty: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut tty) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut tty);
+        if libc::cfsetspeed(&mut tty, speed) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::tcsetattr(fd, libc::TCSANOW, &tty) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn configure_serial(_file: &File, _baud_rate: u32) -> io::Result<()> {
+    Ok(())
+}
+
+macro_rules! serial_sink {
+    ($(#[$meta:meta])* $sink:ident) => {
+        $(#[$meta])*
+        pub struct $sink<W: Write> {
+            writer: W,
+            options: SerialOptions,
+            limiter: RateLimiter,
+            buffer: Vec<u8>,
+        }
+
+        impl<W: Write> $sink<W> {
+            pub fn new(writer: W, options: SerialOptions) -> $sink<W> {
+                $sink {
+                    writer,
+                    limiter: RateLimiter::new(options.max_fps),
+                    options,
+                    buffer: Vec::new(),
+                }
+            }
+
+            pub fn options(&self) -> &SerialOptions {
+                &self.options
+            }
+
+            pub fn get_ref(&self) -> &W {
+                &self.writer
+            }
+
+            pub fn into_inner(self) -> W {
+                self.writer
+            }
+        }
+
+        impl $sink<File> {
+            /// Open a serial device, see `open_serial`
+            pub fn open<P: AsRef<Path>>(
+                path: P,
+                baud_rate: u32,
+                options: SerialOptions,
+            ) -> io::Result<$sink<File>> {
+                Ok($sink::new(open_serial(path, baud_rate)?, options))
+            }
+        }
+
+        impl<W: Write> LedSink for $sink<W> {
+            fn send(&mut self, leds: &[[u8; 3]]) -> io::Result<()> {
+                if !self.limiter.ready() {
+                    return Ok(());
+                }
+
+                self.buffer.clear();
+                self.frame(leds);
+                self.writer.write_all(&self.buffer)?;
+                self.writer.flush()
+            }
+        }
+    };
+}
+
+serial_sink!(
+    /// Writes frames using the Adalight protocol used by Arduino based LED strips.
+    ///
+    /// Every frame starts with `Ada`, the LED count minus one and a checksum. Empty frames
+    /// can't be expressed and aren't written.
+    AdalightSink
+);
+
+serial_sink!(
+    /// Writes frames using TPM2 data frames.
+    Tpm2Sink
+);
+
+impl<W: Write> AdalightSink<W> {
+    fn frame(&mut self, leds: &[[u8; 3]]) {
+        // The header counts from one LED
+        if leds.is_empty() {
+            return;
+        }
+
+        let leds = &leds[..leds.len().min(u16::MAX as usize + 1)];
+        let [hi, lo] = (leds.len().saturating_sub(1) as u16).to_be_bytes();
+        self.buffer.extend_from_slice(b"Ada");
+        self.buffer.extend_from_slice(&[hi, lo, hi ^ lo ^ 0x55]);
+        for led in leds {
+            self.buffer
+                .extend_from_slice(&self.options.byte_order.apply(*led));
+        }
+    }
+}
+
+impl<W: Write> Tpm2Sink<W> {
+    fn frame(&mut self, leds: &[[u8; 3]]) {
+        let leds = &leds[..leds.len().min(u16::MAX as usize / 3)];
+        self.buffer
+            .extend_from_slice(&[TPM2_FRAME_START, TPM2_DATA_FRAME]);
+        self.buffer
+            .extend_from_slice(&((leds.len() * 3) as u16).to_be_bytes());
+        for led in leds {
+            self.buffer
+                .extend_from_slice(&self.options.byte_order.apply(*led));
+        }
+        self.buffer.push(TPM2_FRAME_END);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> SerialOptions {
+        SerialOptions {
+            byte_order: ByteOrder::Grb,
+            max_fps: None,
+        }
+    }
+
+    #[test]
+    fn adalight_header_and_checksum() {
+        let mut sink = AdalightSink::new(Vec::new(), options());
+        sink.send(&[[1, 2, 3], [4, 5, 6]]).unwrap();
+        assert_eq!(
+            sink.get_ref(),
+            &[b'A', b'd', b'a', 0, 1, 0x54, 2, 1, 3, 5, 4, 6]
+        );
+
+        let mut sink = AdalightSink::new(Vec::new(), options());
+        sink.send(&[[0; 3]; 300]).unwrap();
+        let [hi, lo] = 299u16.to_be_bytes();
+        assert_eq!(
+            &sink.get_ref()[..6],
+            &[b'A', b'd', b'a', hi, lo, hi ^ lo ^ 0x55]
+        );
+        assert_eq!(sink.get_ref().len(), 6 + 900);
+    }
+
+    #[test]
+    fn adalight_skips_empty_frames() {
+        let mut sink = AdalightSink::new(Vec::new(), options());
+        sink.send(&[]).unwrap();
+        assert!(sink.get_ref().is_empty());
+    }
+
+    #[test]
+    fn tpm2_framing() {
+        let mut sink = Tpm2Sink::new(Vec::new(), options());
+        sink.send(&[[1, 2, 3], [4, 5, 6]]).unwrap();
+        sink.send(&[]).unwrap();
+        assert_eq!(
+            sink.into_inner(),
+            [
+                0xc9, 0xda, 0, 6, 2, 1, 3, 5, 4, 6, 0x36, 0xc9, 0xda, 0, 0, 0x36
+            ]
+        );
+    }
+}
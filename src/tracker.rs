@@ -0,0 +1,199 @@
+use crate::{Note, Notefinder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A note followed across frames by its enduring id.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TrackedNote {
+    /// Enduring note id assigned by the notefinder
+    pub id: i32,
+    /// Position of the note in the current frame as a fraction of an octave
+    pub position: f32,
+    /// Mean position over the lifetime of the note, averaged around the octave so a note
+    /// wobbling between 0.99 and 0.01 stays near 0
+    pub mean_position: f32,
+    /// Output amplitude in the current frame
+    pub amplitude: f32,
+    /// Highest output amplitude seen so far
+    pub peak_amplitude: f32,
+    /// Number of frames the note has been active
+    pub duration: u64,
+    /// Frame the note started on
+    pub start_frame: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum NoteEvent {
+    /// A note became active
+    On(TrackedNote),
+    /// An active note is still sounding
+    Update(TrackedNote),
+    /// A note is no longer active, contains the final state of the note
+    Off(TrackedNote),
+}
+
+/// Turns successive note frames into start, update and stop events.
+#[derive(Debug, Default)]
+pub struct NoteTracker {
+    frame: u64,
+    active: HashMap<i32, TrackedNote>,
+}
+
+impl NoteTracker {
+    pub fn new() -> NoteTracker {
+        NoteTracker::default()
+    }
+
+    /// Track the notes from the last `run()` or `process()` of the notefinder
+    pub fn track(&mut self, notefinder: &Notefinder) -> Vec<NoteEvent> {
+        self.update(&notefinder.get_notes())
+    }
+
+    /// Feed the next frame of notes and get the resulting events.
+    ///
+    /// `Off` events are emitted before `On` and `Update` events of the same frame, `Off`
+    /// events are ordered by note id.
+    pub fn update(&mut self, notes: &[Note]) -> Vec<NoteEvent> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        let mut stopped: Vec<TrackedNote> = Vec::new();
+        let present = |id: i32| notes.iter().any(|n| n.active && n.endured == id);
+        self.active.retain(|&id, tracked| {
+            let keep = present(id);
+            if !keep {
+                stopped.push(*tracked);
+            }
+            keep
+        });
+        stopped.sort_by_key(|tracked| tracked.id);
+        let mut events: Vec<NoteEvent> = stopped.into_iter().map(NoteEvent::Off).collect();
+
+        for note in notes.iter().filter(|n| n.active) {
+            let id = note.endured;
+            match self.active.get_mut(&id) {
+                Some(tracked) => {
+                    tracked.duration += 1;
+                    tracked.position = note.id;
+                    // Step along the shorter way around the octave
+                    let delta = (note.id - tracked.mean_position + 0.5).rem_euclid(1.0) - 0.5;
+                    tracked.mean_position =
+                        (tracked.mean_position + delta / tracked.duration as f32).rem_euclid(1.0);
+                    tracked.amplitude = note.amplitude_out;
+                    tracked.peak_amplitude = tracked.peak_amplitude.max(note.amplitude_out);
+                    events.push(NoteEvent::Update(*tracked));
+                }
+                None => {
+                    let tracked = TrackedNote {
+                        id,
+                        position: note.id,
+                        mean_position: note.id,
+                        amplitude: note.amplitude_out,
+                        peak_amplitude: note.amplitude_out,
+                        duration: 1,
+                        start_frame: frame,
+                    };
+                    self.active.insert(id, tracked);
+                    events.push(NoteEvent::On(tracked));
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Notes that are currently active
+    pub fn active_notes(&self) -> impl Iterator<Item = &TrackedNote> {
+        self.active.values()
+    }
+
+    /// Number of frames processed so far
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Forget all active notes without emitting `Off` events
+    pub fn reset(&mut self) {
+        self.active.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NoteDists;
+
+    fn note(endured: i32, id: f32) -> Note {
+        Note {
+            active: true,
+            id,
+            dist: NoteDists {
+                amp: 0.0,
+                mean: 0.0,
+                sigma: 0.0,
+                taken: false,
+            },
+            amplitude_out: 0.5,
+            amplitude_iir2: 0.5,
+            endured,
+        }
+    }
+
+    #[test]
+    fn emits_on_update_off() {
+        let mut tracker = NoteTracker::new();
+
+        let events = tracker.update(&[note(1, 0.25)]);
+        assert!(matches!(
+            events[..],
+            [NoteEvent::On(TrackedNote { id: 1, .. })]
+        ));
+
+        let events = tracker.update(&[note(1, 0.25)]);
+        assert!(matches!(
+            events[..],
+            [NoteEvent::Update(TrackedNote {
+                id: 1,
+                duration: 2,
+                ..
+            })]
+        ));
+
+        let events = tracker.update(&[]);
+        assert!(matches!(
+            events[..],
+            [NoteEvent::Off(TrackedNote { id: 1, .. })]
+        ));
+        assert_eq!(tracker.active_notes().count(), 0);
+    }
+
+    #[test]
+    fn mean_position_wraps_around_the_octave() {
+        let mut tracker = NoteTracker::new();
+        for i in 0..10 {
+            let position = if i % 2 == 0 { 0.99 } else { 0.01 };
+            tracker.update(&[note(7, position)]);
+        }
+
+        let mean = tracker.active_notes().next().unwrap().mean_position;
+        let distance = mean.min(1.0 - mean);
+        assert!(distance < 0.02, "mean position {mean}");
+    }
+
+    #[test]
+    fn off_events_are_sorted_by_id() {
+        let mut tracker = NoteTracker::new();
+        let notes: Vec<Note> = [9, 3, 14, 1, 6].iter().map(|&id| note(id, 0.5)).collect();
+        tracker.update(&notes);
+
+        let ids: Vec<i32> = tracker
+            .update(&[])
+            .iter()
+            .map(|event| match event {
+                NoteEvent::Off(tracked) => tracked.id,
+                _ => panic!("expected only off events"),
+            })
+            .collect();
+        assert_eq!(ids, [1, 3, 6, 9, 14]);
+    }
+}
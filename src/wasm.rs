@@ -0,0 +1,125 @@
+//! JavaScript bindings for running the notefinder in the browser.
+//!
+//! Built with the `wasm` feature, which uses the pure Rust backend. Samples and results
+//! cross the boundary as typed arrays, so Web Audio buffers can be pushed as they are.
+//!
+//! ```sh
+//! cargo rustc --release --lib --crate-type cdylib --features wasm --target wasm32-unknown-unknown
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/rustchord.wasm
+//! ```
+//!
+//! ```js
+//! import init, { Notefinder, ccToRgb, noteStride } from "./pkg/rustchord.js";
+//!
+//! await init();
+//! const stride = noteStride();
+//! const nf = new Notefinder(audioContext.sampleRate);
+//! nf.pushSamples(event.inputBuffer.getChannelData(0));
+//! nf.process();
+//! const notes = nf.getNotes();
+//! for (let i = 0; i < notes.length; i += stride) {
+//!     const [r, g, b] = ccToRgb(notes[i], 1.0, notes[i + 1]);
+//! }
+//! ```
+
+use crate::{DFTAlgorithm, Notefinder, ResampleQuality};
+use wasm_bindgen::prelude::*;
+
+/// Values per note in the array returned by `getNotes`: position within the octave,
+/// output amplitude and slow amplitude
+pub const NOTE_STRIDE: usize = 3;
+
+/// `NOTE_STRIDE` for JavaScript
+#[wasm_bindgen(js_name = noteStride)]
+pub fn note_stride() -> usize {
+    NOTE_STRIDE
+}
+
+#[wasm_bindgen(js_name = Notefinder)]
+pub struct WasmNotefinder {
+    nf: Notefinder,
+}
+
+#[wasm_bindgen(js_class = Notefinder)]
+impl WasmNotefinder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(samplerate: i32) -> Result<WasmNotefinder, JsError> {
+        Ok(WasmNotefinder {
+            nf: Notefinder::try_new(samplerate)?,
+        })
+    }
+
+    /// Analyze at `samplerate` while accepting audio at `inputRate`, for example the
+    /// `sampleRate` of the `AudioContext`
+    #[wasm_bindgen(js_name = withInputRate)]
+    pub fn with_input_rate(samplerate: i32, input_rate: i32) -> Result<WasmNotefinder, JsError> {
+        Ok(WasmNotefinder {
+            nf: Notefinder::with_input_rate(samplerate, input_rate, ResampleQuality::default())?,
+        })
+    }
+
+    /// Append mono samples, for example from `AudioBuffer.getChannelData`
+    #[wasm_bindgen(js_name = pushSamples)]
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.nf.push_samples(samples);
+    }
+
+    /// Run the notefinder over the samples pushed since the last call
+    pub fn process(&mut self) {
+        self.nf.process();
+    }
+
+    /// Notes as a flat `Float32Array` with `NOTE_STRIDE` values per note.
+    ///
+    /// Inactive notes have an output amplitude of 0.
+    #[wasm_bindgen(js_name = getNotes)]
+    pub fn get_notes(&self) -> Vec<f32> {
+        self.nf
+            .notes()
+            .flat_map(|note| [note.id, note.amplitude_out, note.amplitude_iir2])
+            .collect()
+    }
+
+    /// Enduring ids of the notes in the same order as `getNotes`, 0 for no note
+    #[wasm_bindgen(js_name = getNoteIds)]
+    pub fn get_note_ids(&self) -> Vec<i32> {
+        self.nf.notes().map(|note| note.endured).collect()
+    }
+
+    /// Folded frequency bins as a `Float32Array`
+    #[wasm_bindgen(js_name = getFolded)]
+    pub fn get_folded(&self) -> Vec<f32> {
+        self.nf.get_folded().to_vec()
+    }
+
+    /// Number of notes returned by `getNotes`
+    #[wasm_bindgen(getter, js_name = notePeaks)]
+    pub fn note_peaks(&self) -> usize {
+        self.nf.note_peaks()
+    }
+
+    #[wasm_bindgen(getter, js_name = frequencyBins)]
+    pub fn frequency_bins(&self) -> i32 {
+        self.nf.frequency_bins()
+    }
+
+    /// Select the DFT algorithm by its index in `DFTAlgorithm`
+    #[wasm_bindgen(js_name = setDftAlgorithm)]
+    pub fn set_dft_algorithm(&mut self, algorithm: i32) -> Result<(), JsError> {
+        let algorithm = DFTAlgorithm::from_raw(algorithm)
+            .ok_or_else(|| JsError::new(&format!("unknown DFT algorithm {algorithm}")))?;
+        self.nf.set_dft_algorithm(algorithm);
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = setAmplification)]
+    pub fn set_amplification(&self, amplification: f32) -> Result<(), JsError> {
+        Ok(self.nf.set_amplification(amplification)?)
+    }
+}
+
+/// Colorchord's note color as a `Float32Array` of red, green and blue in the range 0 - 1
+#[wasm_bindgen(js_name = ccToRgb)]
+pub fn cc_to_rgb(note: f32, saturation: f32, value: f32) -> Vec<f32> {
+    crate::cc_to_rgb(note, saturation, value).to_vec()
+}